@@ -0,0 +1,43 @@
+use backprop_rs::{graph::Graph, optim::stochastic_gradiant_descent};
+
+/// Learn a brightness offset that maps dark images onto their brightened targets
+fn main() {
+    let images = [vec![0.1, 0.2, 0.3], vec![0.0, 0.5, 0.4]];
+    let true_offset = 0.25;
+
+    let g = Graph::new();
+    let offset = g.variable(0.0);
+    let checkpoint = g.len();
+
+    for epoch in 0..50 {
+        let mut loss = g.variable(0.0);
+        for image in images.iter() {
+            let pixels = g.variables(image);
+            let bright = g.apply_brightness(&pixels, offset);
+            for (b, &x) in bright.iter().zip(image) {
+                loss += (*b - g.variable(x + true_offset)).pow(2.);
+            }
+        }
+
+        loss.backward();
+        stochastic_gradiant_descent(&[offset], 0.05);
+
+        if epoch % 10 == 0 {
+            println!(
+                "Epoch {}: Loss = {:.6}, offset = {:.4}",
+                epoch,
+                loss.data(),
+                offset.data()
+            );
+        }
+
+        g.zero_grad();
+        g.truncate(checkpoint);
+    }
+
+    println!(
+        "Learned offset {:.4} (expected: {})",
+        offset.data(),
+        true_offset
+    );
+}
@@ -1,27 +1,53 @@
-use backprop_rs::optim::stochastic_gradiant_descent;
-
-#[path = "dataloader/mnist_loader.rs"]
-mod mnist_loader;
+use backprop_rs::optim::{CosineAnnealingLR, ExponentialLR, stochastic_gradiant_descent};
 
 fn main() {
     mnist();
 }
 
+/// Learning rate schedule, `cargo run --example mnist -- exponential` selects the exponential decay
+enum Scheduler {
+    Cosine(CosineAnnealingLR),
+    Exponential(ExponentialLR),
+}
+
+impl Scheduler {
+    fn from_args(epochs: usize, base_lr: f64) -> Self {
+        match std::env::args().nth(1).as_deref() {
+            Some("exponential") => Scheduler::Exponential(ExponentialLR::new(0.8)),
+            Some("cosine") | None => {
+                Scheduler::Cosine(CosineAnnealingLR::new(epochs, base_lr * 0.01))
+            }
+            Some(other) => panic!("unknown scheduler {other}, use cosine or exponential"),
+        }
+    }
+
+    fn get_lr(&mut self, base_lr: f64) -> f64 {
+        match self {
+            Scheduler::Cosine(s) => s.get_lr(base_lr),
+            Scheduler::Exponential(s) => s.get_lr(base_lr),
+        }
+    }
+}
+
 fn mnist() {
+    use backprop_rs::data::{DataLoader, MnistData, Normalization};
     use backprop_rs::graph::Graph;
-    use mnist_loader::MnistData;
+    use backprop_rs::metrics::accuracy;
+    use backprop_rs::train::{EmaLoss, TrainConfig};
     use std::path::Path;
 
     // Load MNIST data
-    let train = MnistData::load(
+    let train = MnistData::load_normalized(
         Path::new("examples/data/train-images-idx3-ubyte"),
         Path::new("examples/data/train-labels-idx1-ubyte"),
+        Normalization::Standardize,
     )
     .expect("Failed to load MNIST training data");
 
-    let test = MnistData::load(
+    let test = MnistData::load_normalized(
         Path::new("examples/data/t10k-images-idx3-ubyte"),
         Path::new("examples/data/t10k-labels-idx1-ubyte"),
+        train.normalization(),
     )
     .expect("Failed to load MNIST test data");
 
@@ -31,56 +57,67 @@ fn mnist() {
         test.len()
     );
 
+    let config = TrainConfig {
+        lr: 0.01,
+        batch_size: 32,
+        epochs: 10,
+        optimizer: "sgd".to_string(),
+        seed: 0,
+        nin: 784,
+        nouts: vec![16, 10],
+    };
+    config
+        .save(Path::new("examples/data/mnist_config.json"))
+        .expect("Failed to save training config");
+
     let g = Graph::new();
-    let mlp = g.mlp(784, vec![16, 10]);
+    let mlp = g.mlp(config.nin, config.nouts.clone());
     let params = mlp.parameters();
     let checkpoint = g.len();
 
-    println!("Model has {} parameters", params.len());
+    print!("{}", mlp.summary());
 
-    let lr = 0.01;
-    let batch_size = 32;
-    let epochs = 10;
+    let batch_size = config.batch_size;
+    let epochs = config.epochs;
+    let mut scheduler = Scheduler::from_args(epochs, config.lr);
+
+    let mut ema_loss = EmaLoss::new(0.9);
+    let mut loader = DataLoader::new(&train, batch_size, config.seed);
 
     for epoch in 0..epochs {
+        let lr = scheduler.get_lr(config.lr);
         let mut total_loss = 0.0;
         let mut correct = 0;
 
-        let num_samples = 1000; // train.len()
-        for batch_start in (0..num_samples).step_by(batch_size) {
-            let batch_end = (batch_start + batch_size).min(num_samples);
+        let num_batches = 1000 / batch_size; // loader.num_batches()
+        let mut num_samples = 0;
+        for (batch, (images, labels)) in loader.epoch().take(num_batches).enumerate() {
             let mut batch_loss = g.variable(0.0);
+            num_samples += images.len();
+
+            let inputs: Vec<Vec<_>> = images.iter().map(|image| g.variables(image)).collect();
+            let batch_logits = mlp.forward_batch(&inputs);
+
+            for (logits, &target) in batch_logits.iter().zip(&labels) {
+                let loss = g.cross_entropy(logits, target);
+                batch_loss += loss;
 
-            for i in batch_start..batch_end {
-                let inputs: Vec<_> = train.images[i].iter().map(|&x| g.variable(x)).collect();
-                let logits = mlp.forward(&inputs);
-                let probs = g.softmax(&logits);
-                let target = train.labels[i] as usize;
-
-                let loss = g.cross_entropy(&probs, target);
-                batch_loss = batch_loss + loss;
-
-                // Track accuracy
-                let pred = logits
-                    .iter()
-                    .enumerate()
-                    .max_by(|a, b| a.1.data().partial_cmp(&b.1.data()).unwrap())
-                    .map(|(i, _)| i)
-                    .unwrap();
-                if pred == target {
+                if accuracy(logits, target) {
                     correct += 1;
                 }
             }
 
             total_loss += batch_loss.data();
+            let smoothed = ema_loss.update(batch_loss.data());
             batch_loss.backward();
 
-            if batch_start % 32 == 0 {
+            if batch % 8 == 0 {
                 println!(
-                    "Epoch {} - Batch_start: {}: Loss = {:.4}",
+                    "Epoch {} - Batch {}: Loss = {:.4} (EMA {:.4})",
                     epoch,
-                    batch_start,
-                    batch_loss.data()
+                    batch,
+                    batch_loss.data(),
+                    smoothed
                 );
             }
 
@@ -91,27 +128,22 @@ fn mnist() {
             g.truncate(checkpoint);
         }
 
-        let accuracy = correct as f64 / num_samples as f64 * 100.0;
+        let train_accuracy = correct as f64 / num_samples as f64 * 100.0;
         println!(
-            "Epoch {}: Loss = {:.4}, Train Accuracy = {:.2}%",
+            "Epoch {}: Loss = {:.4}, Train Accuracy = {:.2}%, lr = {:.5}",
             epoch + 1,
             total_loss / num_samples as f64,
-            accuracy
+            train_accuracy,
+            lr
         );
 
         // Test accuracy
         let mut test_correct = 0;
         let num_test_samples = 1000;
         for i in 0..num_test_samples {
-            let inputs: Vec<_> = test.images[i].iter().map(|&x| g.variable(x)).collect();
-            let logits = mlp.forward(&inputs);
-            let pred = logits
-                .iter()
-                .enumerate()
-                .max_by(|a, b| a.1.data().partial_cmp(&b.1.data()).unwrap())
-                .map(|(i, _)| i)
-                .unwrap();
-            if pred == test.labels[i] as usize {
+            let inputs = g.variables(&test.images[i]);
+            let logits = g.no_grad(|| mlp.forward(&inputs));
+            if accuracy(&logits, test.labels[i] as usize) {
                 test_correct += 1;
             }
             g.truncate(checkpoint);
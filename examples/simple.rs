@@ -16,6 +16,6 @@ fn main() {
         println!("b|{:.2} | {:?}", b.data(), b.grad());
         println!("a|{:.2} | {:?}", a.data(), a.grad());
 
-        stochastic_gradiant_descent(&mut [a, b], 0.1);
+        stochastic_gradiant_descent(&[a, b], 0.1);
     }
 }
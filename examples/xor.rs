@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 
-use backprop_rs::optim::stochastic_gradiant_descent;
+use backprop_rs::{
+    data::{CsvData, DataLoader},
+    nn::decision_grid,
+    optim::SGD,
+    train::{BatchReduction, Trainer},
+};
 
 fn main() {
     xor();
@@ -10,36 +15,28 @@ fn xor() {
     use backprop_rs::graph::Graph;
     let xs: Vec<[f64; 2]> = vec![[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
     let ys: Vec<f64> = vec![0.0, 1.0, 1.0, 0.0];
+    let data = CsvData {
+        features: xs.iter().map(|x| x.to_vec()).collect(),
+        labels: ys.iter().map(|&y| y as usize).collect(),
+    };
 
     let g = Graph::new();
     let mlp = g.mlp(2, vec![32, 32, 1]);
     let params = mlp.parameters();
-    let checkpoint = g.len();
 
     let lr = 0.01;
 
-    for epoch in 0..500 {
-        let mut total_loss = g.variable(0.0);
-
-        for (x, &target) in xs.iter().zip(ys.iter()) {
-            let inputs = [g.variable(x[0]), g.variable(x[1])];
-            let pred = mlp.forward(&inputs);
-            let y_target = g.variable(target);
-
-            let loss = (pred[0] - y_target).pow(2.);
-            total_loss = total_loss + loss;
-        }
-
-        total_loss.backward();
-
-        stochastic_gradiant_descent(&params, lr);
-
-        if epoch % 100 == 0 {
-            println!("Epoch {}: Loss = {:.4}", epoch, total_loss.data());
-        }
-
-        g.zero_grad();
-        g.truncate(checkpoint);
+    let mut trainer = Trainer::new(
+        &g,
+        &mlp,
+        SGD::new(&params, lr),
+        |pred, target| (pred[0] - target as f64).pow(2.),
+        DataLoader::new(&data, xs.len(), 0),
+    )
+    .with_reduction(BatchReduction::Sum);
+
+    for (epoch, loss) in trainer.fit(500).iter().enumerate().step_by(100) {
+        println!("Epoch {}: Loss = {:.4}", epoch, loss);
     }
 
     println!("\nResults:");
@@ -48,4 +45,14 @@ fn xor() {
         let pred = mlp.forward(&inputs);
         println!("  {:?} -> {:.3} (expected: {})", x, pred[0].data(), target);
     }
+
+    println!("\nDecision boundary (y from 1 down to 0, x from 0 to 1):");
+    let grid = decision_grid(&g, &mlp, (0.0, 1.0), (0.0, 1.0), 11);
+    for row in grid.iter().rev() {
+        let line: String = row
+            .iter()
+            .map(|&v| if v > 0.5 { '#' } else { '.' })
+            .collect();
+        println!("  {}", line);
+    }
 }
@@ -0,0 +1,405 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+use crate::graph::sample_normal;
+
+/// Labeled samples that can be iterated over by a [`DataLoader`]
+pub trait Dataset {
+    fn len(&self) -> usize;
+
+    /// Features and class label of sample `i`
+    fn get(&self, i: usize) -> (&[f64], usize);
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Transform applied to the raw 0-255 pixel values while loading
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Normalization {
+    /// Raw pixel values 0.0-255.0
+    None,
+    /// Scaled into [0, 1]
+    #[default]
+    UnitRange,
+    /// Scaled into [0, 1], then standardized with the mean/std of the MNIST training set
+    Standardize,
+}
+
+impl Normalization {
+    pub const MNIST_MEAN: f64 = 0.1307;
+    pub const MNIST_STD: f64 = 0.3081;
+
+    pub fn apply(&self, pixel: u8) -> f64 {
+        match self {
+            Normalization::None => pixel as f64,
+            Normalization::UnitRange => pixel as f64 / 255.0,
+            Normalization::Standardize => {
+                (pixel as f64 / 255.0 - Self::MNIST_MEAN) / Self::MNIST_STD
+            }
+        }
+    }
+}
+
+pub struct MnistData {
+    pub images: Vec<Vec<f64>>, // each image is 784 floats, see `normalization`
+    pub labels: Vec<u8>,       // 0-9
+    normalization: Normalization,
+}
+
+impl MnistData {
+    /// Load images scaled into [0, 1]. The files may be gzip-compressed.
+    pub fn load(images_path: &Path, labels_path: &Path) -> io::Result<Self> {
+        Self::load_normalized(images_path, labels_path, Normalization::UnitRange)
+    }
+
+    /// Load with the given pixel transform. Pass the `normalization` of the training data when
+    /// loading the test data, so both get the same treatment.
+    pub fn load_normalized(
+        images_path: &Path,
+        labels_path: &Path,
+        normalization: Normalization,
+    ) -> io::Result<Self> {
+        let images = Self::load_images(images_path, normalization)?;
+        let labels = Self::load_labels(labels_path)?;
+        Ok(Self {
+            images,
+            labels,
+            normalization,
+        })
+    }
+
+    /// The transform the pixel values were loaded with
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    fn load_images(path: &Path, normalization: Normalization) -> io::Result<Vec<Vec<f64>>> {
+        let mut reader = open_idx(path)?;
+
+        let magic = read_u32_be(&mut reader)?;
+        check_magic(magic, 2051, "image")?;
+
+        let num_images = read_u32_be(&mut reader)? as usize;
+        let rows = read_u32_be(&mut reader)? as usize;
+        let cols = read_u32_be(&mut reader)? as usize;
+        let pixels_per_image = rows * cols;
+
+        let mut images = Vec::with_capacity(num_images);
+        let mut buffer = vec![0u8; pixels_per_image];
+
+        for _ in 0..num_images {
+            reader.read_exact(&mut buffer)?;
+            let image: Vec<f64> = buffer.iter().map(|&b| normalization.apply(b)).collect();
+            images.push(image);
+        }
+
+        Ok(images)
+    }
+
+    fn load_labels(path: &Path) -> io::Result<Vec<u8>> {
+        let mut reader = open_idx(path)?;
+
+        let magic = read_u32_be(&mut reader)?;
+        check_magic(magic, 2049, "label")?;
+
+        let num_labels = read_u32_be(&mut reader)? as usize;
+
+        let mut labels = vec![0u8; num_labels];
+        reader.read_exact(&mut labels)?;
+
+        Ok(labels)
+    }
+
+    /// Randomly partition the samples into `(train, validation)`, with `val_fraction` of them in
+    /// the validation set
+    pub fn split(&self, val_fraction: f64, seed: u64) -> (MnistData, MnistData) {
+        assert!(
+            (0.0..=1.0).contains(&val_fraction),
+            "validation fraction must be in [0, 1]"
+        );
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        order.shuffle(&mut StdRng::seed_from_u64(seed));
+        let num_val = (self.len() as f64 * val_fraction).round() as usize;
+        let (val, train) = order.split_at(num_val);
+        (self.subset(train), self.subset(val))
+    }
+
+    fn subset(&self, indices: &[usize]) -> MnistData {
+        MnistData {
+            images: indices.iter().map(|&i| self.images[i].clone()).collect(),
+            labels: indices.iter().map(|&i| self.labels[i]).collect(),
+            normalization: self.normalization,
+        }
+    }
+
+    /// One-hot encoding of `label`, e.g. as target for an MSE loss
+    pub fn one_hot(label: u8, num_classes: usize) -> Vec<f64> {
+        assert!(
+            (label as usize) < num_classes,
+            "label {label} out of range for {num_classes} classes"
+        );
+        let mut encoded = vec![0.0; num_classes];
+        encoded[label as usize] = 1.0;
+        encoded
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+}
+
+/// Iterates over a dataset in shuffled mini-batches
+pub struct DataLoader<'d, D: Dataset + ?Sized> {
+    data: &'d D,
+    batch_size: usize,
+    order: Vec<usize>,
+    rng: StdRng,
+    augmentation: Option<Augmentation>,
+}
+
+impl<'d, D: Dataset + ?Sized> DataLoader<'d, D> {
+    pub fn new(data: &'d D, batch_size: usize, seed: u64) -> Self {
+        assert!(batch_size > 0, "batch size must be positive");
+        Self {
+            data,
+            batch_size,
+            order: (0..data.len()).collect(),
+            rng: StdRng::seed_from_u64(seed),
+            augmentation: None,
+        }
+    }
+
+    /// Randomly augment every sample, freshly drawn each epoch
+    pub fn with_augmentation(mut self, augmentation: Augmentation) -> Self {
+        self.augmentation = Some(augmentation);
+        self
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Number of batches per epoch, the last one may be smaller than `batch_size`
+    pub fn num_batches(&self) -> usize {
+        self.order.len().div_ceil(self.batch_size)
+    }
+
+    /// Reshuffle and iterate over all samples once, as `(features, labels)` batches
+    pub fn epoch(&mut self) -> Batches<'_, 'd, D> {
+        self.order.shuffle(&mut self.rng);
+        Batches {
+            data: self.data,
+            chunks: self.order.chunks(self.batch_size),
+            augmentation: self.augmentation.map(|a| (a, &mut self.rng)),
+        }
+    }
+}
+
+/// Batches of one epoch, see [`DataLoader::epoch`]
+pub struct Batches<'a, 'd, D: Dataset + ?Sized> {
+    data: &'d D,
+    chunks: std::slice::Chunks<'a, usize>,
+    augmentation: Option<(Augmentation, &'a mut StdRng)>,
+}
+
+impl<'d, D: Dataset + ?Sized> Iterator for Batches<'_, 'd, D> {
+    /// Features are only copied when augmented
+    type Item = (Vec<Cow<'d, [f64]>>, Vec<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let indices = self.chunks.next()?;
+        let batch = indices.iter().map(|&i| {
+            let (features, label) = self.data.get(i);
+            match &mut self.augmentation {
+                Some((augmentation, rng)) => (Cow::Owned(augmentation.apply(features, rng)), label),
+                None => (Cow::Borrowed(features), label),
+            }
+        });
+        Some(batch.unzip())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+/// Random shift and noise applied to images by the [`DataLoader`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Augmentation {
+    pub width: usize,
+    pub height: usize,
+    /// Shift by up to this many pixels in each direction, padding with zeros
+    pub max_shift: usize,
+    /// Standard deviation of the additive Gaussian noise
+    pub noise_std: f64,
+}
+
+impl Augmentation {
+    /// Shifts of up to 2 pixels and a little noise on 28x28 images
+    pub fn mnist() -> Self {
+        Self {
+            width: 28,
+            height: 28,
+            max_shift: 2,
+            noise_std: 0.05,
+        }
+    }
+
+    /// Augment a single image of `width * height` pixels
+    pub fn apply<R: Rng + ?Sized>(&self, image: &[f64], rng: &mut R) -> Vec<f64> {
+        assert_eq!(
+            image.len(),
+            self.width * self.height,
+            "image does not match the augmentation size"
+        );
+        let max = self.max_shift as i64;
+        let dx = rng.random_range(-max..=max);
+        let dy = rng.random_range(-max..=max);
+
+        let mut out = vec![0.0; image.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (sx, sy) = (x as i64 - dx, y as i64 - dy);
+                if (0..self.width as i64).contains(&sx) && (0..self.height as i64).contains(&sy) {
+                    out[y * self.width + x] = image[sy as usize * self.width + sx as usize];
+                }
+            }
+        }
+        if self.noise_std > 0.0 {
+            for pixel in out.iter_mut() {
+                *pixel += sample_normal(0.0, self.noise_std, rng);
+            }
+        }
+        out
+    }
+}
+
+impl Dataset for MnistData {
+    fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    fn get(&self, i: usize) -> (&[f64], usize) {
+        (&self.images[i], self.labels[i] as usize)
+    }
+}
+
+/// Numeric features with one integer label column, parsed from CSV
+pub struct CsvData {
+    pub features: Vec<Vec<f64>>,
+    pub labels: Vec<usize>,
+}
+
+impl CsvData {
+    pub fn load(path: &Path, label_column: usize) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?), label_column)
+    }
+
+    /// Parse CSV rows, a first line without any numeric field is skipped as header
+    pub fn from_reader<R: BufRead>(reader: R, label_column: usize) -> io::Result<Self> {
+        let mut data = CsvData {
+            features: Vec::new(),
+            labels: Vec::new(),
+        };
+        let mut num_columns = None;
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let row = match parse_row(&fields, label_column) {
+                Ok(row) => row,
+                Err(_) if i == 0 && fields.iter().all(|f| f.parse::<f64>().is_err()) => continue,
+                Err(msg) => return Err(invalid_row(i + 1, msg)),
+            };
+            match num_columns {
+                None => num_columns = Some(fields.len()),
+                Some(n) if n != fields.len() => {
+                    let msg = format!("expected {n} columns, found {}", fields.len());
+                    return Err(invalid_row(i + 1, msg));
+                }
+                Some(_) => {}
+            }
+            data.features.push(row.0);
+            data.labels.push(row.1);
+        }
+        Ok(data)
+    }
+
+    pub fn len(&self) -> usize {
+        self.features.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+}
+
+impl Dataset for CsvData {
+    fn len(&self) -> usize {
+        self.features.len()
+    }
+
+    fn get(&self, i: usize) -> (&[f64], usize) {
+        (&self.features[i], self.labels[i])
+    }
+}
+
+fn parse_row(fields: &[&str], label_column: usize) -> Result<(Vec<f64>, usize), String> {
+    let label = fields
+        .get(label_column)
+        .ok_or_else(|| format!("missing label column {label_column}"))?;
+    let label = label
+        .parse()
+        .map_err(|_| format!("invalid label {label:?}"))?;
+    let features = fields
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != label_column)
+        .map(|(_, f)| f.parse().map_err(|_| format!("invalid value {f:?}")))
+        .collect::<Result<_, _>>()?;
+    Ok((features, label))
+}
+
+fn invalid_row(line: usize, msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("line {line}: {msg}"))
+}
+
+/// Open an IDX file, transparently decompressing it if it is gzipped
+fn open_idx(path: &Path) -> io::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(BufReader::new(GzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+fn read_u32_be<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn check_magic(magic: u32, expected: u32, kind: &str) -> io::Result<()> {
+    if magic != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid {kind} file magic number {magic}, expected {expected}"),
+        ));
+    }
+    Ok(())
+}
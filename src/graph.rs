@@ -1,8 +1,11 @@
 #![allow(dead_code)]
+use rand::{Rng, RngCore, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
 use std::{
-    cell::RefCell,
+    borrow::Cow,
+    cell::{Cell, RefCell, RefMut},
     collections::HashSet,
-    ops::{Add, Div, Mul, Neg, Sub},
+    ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign},
 };
 
 pub type VariableDataIdx = usize;
@@ -12,28 +15,82 @@ pub type VariableDataIdx = usize;
 #[derive(Debug)]
 pub struct Graph {
     vars: RefCell<Vec<VariableData>>,
+    rng: RefCell<SeededRng>,
+    no_grad: Cell<bool>,
+    free: RefCell<Vec<VariableDataIdx>>, // released slots, reused before the arena grows
+    reused: RefCell<Vec<VariableDataIdx>>, // slots taken from free in allocation order, see truncate
+    grad_var_owners: RefCell<Vec<VariableDataIdx>>, // variables whose grad_var may be set
+    visited: RefCell<Vec<bool>>,           // reusable buffer of topo, all false between calls
+    scratch: RefCell<Vec<f64>>, // reusable children data buffer of push_var and backward_single
+    grad_scratch: RefCell<Vec<f64>>, // reusable gradient buffer of backward_single
+    panic_on_nan: Cell<bool>,   // see set_panic_on_nan
 }
 
 impl Graph {
+    /// Graph with a random seed, see `seed` to record it for a later replay
     pub fn new() -> Self {
+        Self::with_seed(rand::random())
+    }
+
+    /// Graph with room for `n` variables before the arena reallocates
+    pub fn with_capacity(n: usize) -> Self {
+        let g = Self::new();
+        g.vars.borrow_mut().reserve(n);
+        g
+    }
+
+    /// All stochastic ops on this graph draw from one rng, so a single seed reproduces a whole run
+    pub fn with_seed(seed: u64) -> Self {
         Self {
             vars: RefCell::new(Vec::new()),
+            rng: RefCell::new(SeededRng::new(seed)),
+            no_grad: Cell::new(false),
+            free: RefCell::new(Vec::new()),
+            reused: RefCell::new(Vec::new()),
+            grad_var_owners: RefCell::new(Vec::new()),
+            visited: RefCell::new(Vec::new()),
+            scratch: RefCell::new(Vec::new()),
+            grad_scratch: RefCell::new(Vec::new()),
+            panic_on_nan: Cell::new(false),
         }
     }
 
+    /// The seed the graph's rng was created with
+    pub fn seed(&self) -> u64 {
+        self.rng.borrow().seed
+    }
+
+    /// The shared random number generator, e.g. for dropout masks
+    pub fn rng(&self) -> RefMut<'_, SeededRng> {
+        self.rng.borrow_mut()
+    }
+
     /// Construct a new variable with data
     pub fn variable(&self, data: f64) -> Variable<'_> {
-        let mut vars = self.vars.borrow_mut();
-        let idx = vars.len();
-        vars.push(VariableData::new(data));
+        let idx = self.alloc(VariableData::new(data));
         Variable { idx, graph: self }
     }
 
+    /// Construct a variable per element of `data` with a single borrow of the arena
+    pub fn variables(&self, data: &[f64]) -> Vec<Variable<'_>> {
+        let mut vars = self.vars.borrow_mut();
+        let mut free = self.free.borrow_mut();
+        let mut reused = self.reused.borrow_mut();
+        data.iter()
+            .map(|&d| Variable {
+                idx: alloc_in(&mut vars, &mut free, &mut reused, VariableData::new(d)),
+                graph: self,
+            })
+            .collect()
+    }
+
     /// Set all gradients to zero
     pub fn zero_grad(&self) {
         for var in self.vars.borrow_mut().iter_mut() {
             var.grad = None;
+            var.grad_var = None;
         }
+        self.grad_var_owners.borrow_mut().clear();
     }
 
     /// The number of variables
@@ -46,10 +103,147 @@ impl Graph {
         self.vars.borrow().is_empty()
     }
 
+    /// Run `f` in inference mode: ops only compute their value and are recorded as plain leaves
+    /// without children, so nothing inside the scope can be backpropagated through
+    pub fn no_grad<R>(&self, f: impl FnOnce() -> R) -> R {
+        // restores the outer mode even if `f` panics
+        struct Restore<'g>(&'g Cell<bool>, bool);
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.1);
+            }
+        }
+        let _restore = Restore(&self.no_grad, self.no_grad.replace(true));
+        f()
+    }
+
+    /// Run `f` with the gradients of `vars` cleared, then truncate the graph back to its current
+    /// length and restore the gradients. Lets helpers backpropagate through a model without
+    /// clobbering gradients the caller has accumulated on its parameters.
+    pub(crate) fn with_scratch_grads<R>(&self, vars: &[Variable<'_>], f: impl FnOnce() -> R) -> R {
+        let checkpoint = self.len();
+        let saved: Vec<_> = {
+            let mut data = self.vars.borrow_mut();
+            vars.iter().map(|v| data[v.idx].grad.take()).collect()
+        };
+        let result = f();
+        self.truncate(checkpoint);
+        let mut data = self.vars.borrow_mut();
+        for (v, grad) in vars.iter().zip(saved) {
+            data[v.idx].grad = grad;
+        }
+        result
+    }
+
+    /// Index of the first variable whose data or gradient is NaN or infinite
+    pub fn check_finite(&self) -> Result<(), VariableDataIdx> {
+        let vars = self.vars.borrow();
+        match vars
+            .iter()
+            .position(|v| !v.data.is_finite() || v.grad.is_some_and(|g| !g.is_finite()))
+        {
+            Some(idx) => Err(idx),
+            None => Ok(()),
+        }
+    }
+
+    /// Panic as soon as an op computes a NaN/inf value or gradient, naming the op.
+    /// Useful while debugging, e.g. to catch a `log(0)` right where it happens.
+    pub fn set_panic_on_nan(&self, enabled: bool) {
+        self.panic_on_nan.set(enabled);
+    }
+
     /// Remove all elements from len onwards.
-    /// Useful to reset graph after computation if model is initialized first
+    /// Useful to reset graph after computation if model is initialized first.
+    /// Variables created after the checkpoint in released slots below `len` are removed as well if
+    /// they depend on anything removed, so no surviving variable points past the end of the arena.
     pub fn truncate(&self, len: usize) {
-        self.vars.borrow_mut().truncate(len);
+        let mut vars = self.vars.borrow_mut();
+        let mut free = self.free.borrow_mut();
+        free.retain(|&idx| idx < len);
+        if len >= vars.len() {
+            return;
+        }
+        vars.truncate(len);
+
+        // only reused slots can hold variables created after the checkpoint below len. They are
+        // listed in allocation order and children are created before their parents, so one pass
+        // sees every removed child before its parents.
+        let mut reused = self.reused.borrow_mut();
+        reused.retain(|&idx| idx < len);
+        let mut removed = self.visited.borrow_mut();
+        if removed.len() < len {
+            removed.resize(len, false);
+        }
+        let first_removed = free.len();
+        for &idx in reused.iter() {
+            if vars[idx].children.iter().any(|&c| c >= len || removed[c]) {
+                vars[idx] = VariableData::new(0.0);
+                removed[idx] = true;
+                free.push(idx);
+            }
+        }
+        reused.retain(|&idx| !removed[idx]);
+
+        // gradient variables that are gone, later variables may take their slots
+        self.grad_var_owners.borrow_mut().retain(|&idx| {
+            if idx >= len || removed[idx] {
+                return false;
+            }
+            let var = &mut vars[idx];
+            var.grad_var = var.grad_var.filter(|&g| g < len && !removed[g]);
+            var.grad_var.is_some()
+        });
+        for &idx in &free[first_removed..] {
+            removed[idx] = false;
+        }
+    }
+
+    /// Release the slots of variables that are no longer needed, later variables reuse them.
+    /// The handles must not be used afterwards. Panics if another variable still depends on one of them.
+    pub fn free(&self, vars: &[Variable<'_>]) {
+        let released: HashSet<_> = vars.iter().map(|v| v.idx).collect();
+        let mut free = self.free.borrow_mut();
+        let already_free: HashSet<_> = free.iter().copied().collect();
+        let mut data = self.vars.borrow_mut();
+        for (idx, var) in data.iter().enumerate() {
+            if released.contains(&idx) || already_free.contains(&idx) {
+                continue;
+            }
+            if let Some(c) = var.children.iter().find(|c| released.contains(c)) {
+                panic!("free: variable {c} is still used by variable {idx}");
+            }
+        }
+        for var in data.iter_mut() {
+            var.grad_var = var.grad_var.filter(|g| !released.contains(g));
+        }
+        self.reused
+            .borrow_mut()
+            .retain(|idx| !released.contains(idx));
+        for idx in released {
+            data[idx] = VariableData::new(0.0);
+            if !already_free.contains(&idx) {
+                free.push(idx);
+            }
+        }
+    }
+
+    /// Drop released slots at the end of the arena and shrink its allocation
+    pub fn compact(&self) {
+        let mut free = self.free.borrow_mut();
+        let mut vars = self.vars.borrow_mut();
+        free.sort_unstable();
+        while free.last().is_some_and(|&idx| idx + 1 == vars.len()) {
+            free.pop();
+            vars.pop();
+        }
+        vars.shrink_to_fit();
+        free.shrink_to_fit();
+    }
+
+    /// Capacity of the arena, i.e. the number of variables it can hold without reallocating
+    pub fn capacity(&self) -> usize {
+        self.vars.borrow().capacity()
     }
 
     /// Convenience function to create a single neuron
@@ -77,31 +271,328 @@ impl Graph {
         let max_var = self.variable(max_val);
 
         let exps: Vec<Variable<'_>> = logits.iter().map(|x| (*x - max_var).exp()).collect();
-        let sum_exp = exps.iter().skip(1).fold(exps[0], |acc, &x| acc + x);
+        let sum_exp = self.sum(&exps);
 
         exps.iter().map(|&e| e / sum_exp).collect()
     }
 
-    /// Compute the cross entropy
-    pub fn cross_entropy<'a>(&'a self, probs: &[Variable<'a>], target: usize) -> Variable<'a> {
-        -probs[target].log()
+    /// Sum as a balanced tree of additions, so the graph depth grows only logarithmically.
+    /// An empty slice sums to a zero constant.
+    pub fn sum<'a>(&'a self, xs: &[Variable<'a>]) -> Variable<'a> {
+        if xs.is_empty() {
+            return self.variable(0.0);
+        }
+        let mut level = xs.to_vec();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match *pair {
+                    [a, b] => a + b,
+                    [a] => a,
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        level[0]
+    }
+
+    /// Mean of the elements, see `sum`. An empty slice has mean zero.
+    pub fn mean<'a>(&'a self, xs: &[Variable<'a>]) -> Variable<'a> {
+        if xs.is_empty() {
+            return self.variable(0.0);
+        }
+        self.sum(xs) / xs.len() as f64
+    }
+
+    /// Compute the dot product of two equally long slices, the products are summed as a balanced tree
+    pub fn dot<'a>(&'a self, a: &[Variable<'a>], b: &[Variable<'a>]) -> Variable<'a> {
+        assert_eq!(a.len(), b.len(), "dot: slices must have equal length");
+        let products: Vec<_> = a.iter().zip(b).map(|(&x, &y)| x * y).collect();
+        self.sum(&products)
+    }
+
+    /// Sum of squared parameter values, add `lambda * l2_penalty(params)` to the loss for weight decay.
+    /// Build it anew every step like the rest of the loss, it's removed by `truncate` too.
+    pub fn l2_penalty<'a>(&'a self, params: &[Variable<'a>]) -> Variable<'a> {
+        let squares: Vec<_> = params.iter().map(|p| p.pow(2.0)).collect();
+        self.sum(&squares)
+    }
+
+    /// Sum of absolute parameter values, pushes weights towards exactly zero (see `l2_penalty`).
+    /// The subgradient of a zero parameter is zero.
+    pub fn l1_penalty<'a>(&'a self, params: &[Variable<'a>]) -> Variable<'a> {
+        let magnitudes: Vec<_> = params.iter().map(|p| p.abs()).collect();
+        self.sum(&magnitudes)
+    }
+
+    /// Compute the cosine similarity dot(a, b) / (||a|| * ||b||).
+    /// A small epsilon inside the norms keeps zero vectors finite.
+    pub fn cosine_similarity<'a>(&'a self, a: &[Variable<'a>], b: &[Variable<'a>]) -> Variable<'a> {
+        const EPS: f64 = 1e-12;
+        let eps = self.variable(EPS);
+        let norm_a = (self.dot(a, a) + eps).sqrt();
+        let norm_b = (self.dot(b, b) + eps).sqrt();
+        self.dot(a, b) / (norm_a * norm_b)
+    }
+
+    /// Add a shared (learnable) brightness offset to every pixel
+    pub fn apply_brightness<'a>(
+        &'a self,
+        pixels: &[Variable<'a>],
+        offset: Variable<'a>,
+    ) -> Vec<Variable<'a>> {
+        pixels.iter().map(|&p| p + offset).collect()
+    }
+
+    /// Total variation sum(|x[i+1] - x[i]|) of a 1D signal
+    pub fn total_variation<'a>(&'a self, xs: &[Variable<'a>]) -> Variable<'a> {
+        xs.windows(2)
+            .fold(self.variable(0.0), |acc, w| acc + (w[1] - w[0]).abs())
+    }
+
+    /// Log-determinant of a diagonal matrix sum(log(|d| + eps)), e.g. for normalizing flows
+    pub fn log_det_diag<'a>(&'a self, diag: &[Variable<'a>]) -> Variable<'a> {
+        const EPS: f64 = 1e-12;
+        let eps = self.variable(EPS);
+        diag.iter()
+            .fold(self.variable(0.0), |acc, &d| acc + (d.abs() + eps).log())
+    }
+
+    /// Fused affine map b + sum(w[i] * x[i]) as a single node. The products aren't stored in the
+    /// arena but recomputed from w and x during backward, trading compute for memory.
+    pub fn affine<'a>(
+        &'a self,
+        w: &[Variable<'a>],
+        x: &[Variable<'a>],
+        b: Variable<'a>,
+    ) -> Variable<'a> {
+        assert_eq!(
+            w.len(),
+            x.len(),
+            "affine: weights and inputs must have equal length"
+        );
+        for v in w.iter().chain(x) {
+            b.assert_same_graph(*v);
+        }
+        let children = std::iter::once(b.idx)
+            .chain(w.iter().map(|v| v.idx))
+            .chain(x.iter().map(|v| v.idx))
+            .collect();
+        let idx = self.push_var(children, Op::Affine);
+        Variable::new(idx, self)
+    }
+
+    /// Add gaussian noise N(0, std^2) drawn from the graph's rng to every element
+    pub fn gaussian_noise<'a>(&'a self, x: &[Variable<'a>], std: f64) -> Vec<Variable<'a>> {
+        x.iter()
+            .map(|&xi| {
+                let n = sample_normal(0.0, std, &mut *self.rng());
+                xi + self.variable(n)
+            })
+            .collect()
+    }
+
+    /// Compute the softmax cross entropy of `logits`, panics if `target` is not a valid class
+    /// (see `try_cross_entropy`)
+    pub fn cross_entropy<'a>(&'a self, logits: &[Variable<'a>], target: usize) -> Variable<'a> {
+        self.try_cross_entropy(logits, target)
+            .unwrap_or_else(|e| panic!("cross_entropy: {e}"))
+    }
+
+    /// Compute the softmax cross entropy of `logits`, errors if `target` is out of range (e.g. a
+    /// corrupt label)
+    pub fn try_cross_entropy<'a>(
+        &'a self,
+        logits: &[Variable<'a>],
+        target: usize,
+    ) -> Result<Variable<'a>, LossError> {
+        let logit = logits.get(target).ok_or(LossError::TargetOutOfRange {
+            target,
+            num_classes: logits.len(),
+        })?;
+        // fused log-softmax: even if the target probability underflows, the loss stays finite and
+        // the gradient is still softmax - onehot
+        Ok(self.logsumexp(logits) - *logit)
+    }
+
+    /// log(sum(exp(xs))), shifted by the max so the exponentials can't overflow and the sum is at
+    /// least 1
+    fn logsumexp<'a>(&'a self, xs: &[Variable<'a>]) -> Variable<'a> {
+        let max_val = xs
+            .iter()
+            .map(|v| v.data())
+            .fold(f64::NEG_INFINITY, f64::max);
+        let exps: Vec<_> = xs.iter().map(|&x| (x - max_val).exp()).collect();
+        self.sum(&exps).log() + max_val
+    }
+
+    /// Jacobian d outputs[i] / d inputs[j], one backward pass per output.
+    /// Clears all gradients of the graph.
+    pub fn jacobian<'a>(
+        &'a self,
+        outputs: &[Variable<'a>],
+        inputs: &[Variable<'a>],
+    ) -> Vec<Vec<f64>> {
+        let jacobian = outputs
+            .iter()
+            .map(|out| {
+                self.zero_grad();
+                out.backward();
+                inputs
+                    .iter()
+                    .map(|x| x.grad().unwrap_or_default())
+                    .collect()
+            })
+            .collect();
+        self.zero_grad();
+        jacobian
+    }
+
+    /// Sign (-1, 0 or 1) of the determinant of the square Jacobian, e.g. to check that a
+    /// normalizing flow transform is orientation preserving
+    pub fn jacobian_sign<'a>(&'a self, outputs: &[Variable<'a>], inputs: &[Variable<'a>]) -> i8 {
+        assert_eq!(
+            outputs.len(),
+            inputs.len(),
+            "jacobian_sign: jacobian must be square"
+        );
+        let det = determinant(self.jacobian(outputs, inputs));
+        if det > 0.0 {
+            1
+        } else if det < 0.0 {
+            -1
+        } else {
+            0
+        }
+    }
+
+    /// Serialize all variables (data, grad, children, op) and the released slots, e.g. to attach a
+    /// graph to a bug report
+    pub fn to_json(&self) -> String {
+        let vars = self.vars.borrow();
+        let free = self.free.borrow();
+        let json = GraphJson {
+            vars: Cow::Borrowed(&vars),
+            free: Cow::Borrowed(&free),
+        };
+        serde_json::to_string(&json).expect("graph is always serializable")
+    }
+
+    /// Reload a graph stored by `to_json`, variables keep their indices and released slots stay
+    /// released. Errors if an op has the wrong number of children, a child is out of range or
+    /// released, or the variables form a cycle.
+    pub fn from_json(s: &str) -> serde_json::Result<Self> {
+        let json: GraphJson = serde_json::from_str(s)?;
+        let (vars, free) = (json.vars.into_owned(), json.free.into_owned());
+        let invalid = |msg: String| Err(serde::de::Error::custom(msg));
+
+        let mut released = vec![false; vars.len()];
+        for &idx in &free {
+            if idx >= vars.len() || std::mem::replace(&mut released[idx], true) {
+                return invalid(format!("invalid released slot {idx}"));
+            }
+            if !vars[idx].children.is_empty() {
+                return invalid(format!("released slot {idx} has children"));
+            }
+        }
+        for (idx, var) in vars.iter().enumerate() {
+            if !var.op.accepts_children(var.children.len()) {
+                return invalid(format!(
+                    "variable {idx} has {} children, invalid for {}",
+                    var.children.len(),
+                    var.op.name()
+                ));
+            }
+            if var.children.iter().any(|&c| c >= vars.len() || released[c]) {
+                return invalid(format!("variable {idx} references an invalid child"));
+            }
+        }
+        if let Some(idx) = find_cycle(&vars) {
+            return invalid(format!("variable {idx} depends on itself"));
+        }
+
+        let g = Graph::new();
+        *g.vars.borrow_mut() = vars;
+        *g.free.borrow_mut() = free;
+        Ok(g)
+    }
+
+    /// Handle to the variable at arena index `idx`, e.g. for a graph loaded with `from_json`
+    pub fn variable_at(&self, idx: usize) -> Option<Variable<'_>> {
+        (idx < self.len()).then(|| Variable::new(idx, self))
+    }
+
+    /// Graphviz DOT digraph of everything `root` depends on, similar to micrograd's `draw_dot`.
+    /// Nodes are named by arena index and labeled with op, data and grad, edges go from child to parent.
+    pub fn to_dot(&self, root: Variable<'_>) -> String {
+        let topo = self.topo(&[root.idx]);
+        let vars = self.vars.borrow();
+        let mut dot = String::from("digraph {\n    rankdir=LR;\n    node [shape=record];\n");
+        for &v in &topo {
+            let var = &vars[v];
+            let grad = var.grad.map_or("-".to_string(), |g| format!("{g:.4}"));
+            dot += &format!(
+                "    n{v} [label=\"{{ #{v} | {} | data {:.4} | grad {grad} }}\"];\n",
+                var.op.name(),
+                var.data
+            );
+        }
+        for &v in &topo {
+            for c in &vars[v].children {
+                dot += &format!("    n{c} -> n{v};\n");
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+
+    /// Plain (index, op name, data, children) entry per node, for rendering the graph with external tools
+    pub fn adjacency(&self) -> Vec<(usize, &'static str, f64, Vec<usize>)> {
+        self.vars
+            .borrow()
+            .iter()
+            .enumerate()
+            .map(|(idx, var)| (idx, var.op.name(), var.data, var.children.clone()))
+            .collect()
     }
 
     // Internal arena operations
 
     /// Add a new computation variable, forward path is executed directly
     fn push_var(&self, children: Vec<VariableDataIdx>, op: Op) -> VariableDataIdx {
-        let mut vars = self.vars.borrow_mut();
-        let children_data: Vec<f64> = children.iter().map(|&c| vars[c].data).collect();
-        let data = op.forward(&children_data);
-        let idx = vars.len();
-        vars.push(VariableData {
+        let data = {
+            let vars = self.vars.borrow();
+            let mut children_data = self.scratch.borrow_mut();
+            children_data.clear();
+            children_data.extend(children.iter().map(|&c| vars[c].data));
+            op.forward(&children_data)
+        };
+        if self.panic_on_nan.get() && !data.is_finite() {
+            panic!("{} op computed the non-finite value {data}", op.name());
+        }
+        let (children, op) = if self.no_grad.get() {
+            (Vec::new(), Op::Value)
+        } else {
+            (children, op)
+        };
+        self.alloc(VariableData {
             data,
             grad: None,
             children,
             op,
-        });
-        idx
+            requires_grad: true,
+            grad_var: None,
+        })
+    }
+
+    /// Store a variable in a released slot if there is one, otherwise at the end of the arena
+    fn alloc(&self, var: VariableData) -> VariableDataIdx {
+        alloc_in(
+            &mut self.vars.borrow_mut(),
+            &mut self.free.borrow_mut(),
+            &mut self.reused.borrow_mut(),
+            var,
+        )
     }
 
     /// data Getter
@@ -119,9 +610,31 @@ impl Graph {
         self.vars.borrow_mut()[idx].data = data;
     }
 
+    /// grad Setter
+    fn set_grad(&self, idx: VariableDataIdx, grad: f64) {
+        self.vars.borrow_mut()[idx].grad = Some(grad);
+    }
+
+    /// requires_grad Getter
+    fn requires_grad(&self, idx: VariableDataIdx) -> bool {
+        self.vars.borrow()[idx].requires_grad
+    }
+
+    /// requires_grad Setter
+    fn set_requires_grad(&self, idx: VariableDataIdx, requires_grad: bool) {
+        self.vars.borrow_mut()[idx].requires_grad = requires_grad;
+    }
+
     /// Remove grad for single variable
     fn zero_grad_single(&self, idx: VariableDataIdx) {
-        self.vars.borrow_mut()[idx].grad = None;
+        let var = &mut self.vars.borrow_mut()[idx];
+        var.grad = None;
+        var.grad_var = None;
+    }
+
+    /// grad_var Getter, None once the gradient variable was truncated or freed
+    fn grad_var(&self, idx: VariableDataIdx) -> Option<VariableDataIdx> {
+        self.vars.borrow()[idx].grad_var
     }
 
     /// Add add op variable, normally used by Variable
@@ -134,6 +647,16 @@ impl Graph {
         self.push_var(vec![a, b], Op::Mul)
     }
 
+    /// Add scalar add op variable, normally used by Variable
+    fn add_scalar_op(&self, a: VariableDataIdx, s: f64) -> VariableDataIdx {
+        self.push_var(vec![a], Op::AddScalar(s))
+    }
+
+    /// Add scalar mul op variable, normally used by Variable
+    fn mul_scalar_op(&self, a: VariableDataIdx, s: f64) -> VariableDataIdx {
+        self.push_var(vec![a], Op::MulScalar(s))
+    }
+
     /// Add pow op variable, normally used by Variable
     fn pow_op(&self, a: VariableDataIdx, exp: f64) -> VariableDataIdx {
         self.push_var(vec![a], Op::Pow(exp))
@@ -144,6 +667,36 @@ impl Graph {
         self.push_var(vec![a], Op::ReLU)
     }
 
+    /// Add leaky relu op variable, normally used by Variable
+    fn leaky_relu_op(&self, a: VariableDataIdx, slope: f64) -> VariableDataIdx {
+        self.push_var(vec![a], Op::LeakyReLU(slope))
+    }
+
+    /// Add tanh op variable, normally used by Variable
+    fn tanh_op(&self, a: VariableDataIdx) -> VariableDataIdx {
+        self.push_var(vec![a], Op::Tanh)
+    }
+
+    /// Add sigmoid op variable, normally used by Variable
+    fn sigmoid_op(&self, a: VariableDataIdx) -> VariableDataIdx {
+        self.push_var(vec![a], Op::Sigmoid)
+    }
+
+    /// Add hard sigmoid op variable, normally used by Variable
+    fn hard_sigmoid_op(&self, a: VariableDataIdx) -> VariableDataIdx {
+        self.push_var(vec![a], Op::HardSigmoid)
+    }
+
+    /// Add softsign op variable, normally used by Variable
+    fn softsign_op(&self, a: VariableDataIdx) -> VariableDataIdx {
+        self.push_var(vec![a], Op::Softsign)
+    }
+
+    /// Add clamped ln op variable, normally used by Variable
+    fn log_clamped_op(&self, a: VariableDataIdx, eps: f64) -> VariableDataIdx {
+        self.push_var(vec![a], Op::LogClamped(eps))
+    }
+
     /// Add exp op variable, normally used by Variable
     fn exp_op(&self, a: VariableDataIdx) -> VariableDataIdx {
         self.push_var(vec![a], Op::Exp)
@@ -154,10 +707,14 @@ impl Graph {
         self.push_var(vec![a], Op::Log)
     }
 
-    /// Add neg op variable, normally used by Variable
+    /// Add abs op variable, normally used by Variable
+    fn abs_op(&self, a: VariableDataIdx) -> VariableDataIdx {
+        self.push_var(vec![a], Op::Abs)
+    }
+
+    /// Add neg op variable (a scalar multiplication by -1), normally used by Variable
     fn neg_op(&self, a: VariableDataIdx) -> VariableDataIdx {
-        let minus_one = self.variable(-1.0).idx;
-        self.mul_op(a, minus_one)
+        self.mul_scalar_op(a, -1.0)
     }
 
     /// Add sub op variable (using neg and add), normally used by Variable
@@ -175,15 +732,31 @@ impl Graph {
     /// Backpropagate gradients for a single variable to its children
     fn backward_single(&self, a: VariableDataIdx) {
         let mut vars = self.vars.borrow_mut();
+        if vars[a].children.is_empty() {
+            return; // leaf
+        }
 
-        // calc grads depending on op type
-        let children_data: Vec<f64> = vars[a].children.iter().map(|&c| vars[c].data).collect();
-        let grads = vars[a].op.backward(
+        // calc grads depending on op type, the children data buffer is reused across calls
+        let mut children_data = self.scratch.borrow_mut();
+        children_data.clear();
+        children_data.extend(vars[a].children.iter().map(|&c| vars[c].data));
+        let mut grads = self.grad_scratch.borrow_mut();
+        vars[a].op.backward(
             &children_data,
             vars[a].data,
             vars[a].grad.unwrap_or_default(),
+            &mut grads,
         );
 
+        if self.panic_on_nan.get()
+            && let Some(grad) = grads.iter().find(|g| !g.is_finite())
+        {
+            panic!(
+                "{} op of variable {a} backpropagated the non-finite gradient {grad}",
+                vars[a].op.name()
+            );
+        }
+
         // accumulate grads
         for (i, grad) in grads.iter().enumerate() {
             let child = vars[a].children[i];
@@ -192,34 +765,233 @@ impl Graph {
         }
     }
 
-    /// Backpropagate gradiants through the graph
-    fn backward(&self, idx: VariableDataIdx) {
+    /// Topological order of all variables the roots depend on (including themselves), children first.
+    /// Depth first with an explicit stack, so deep graphs (e.g. long chains) can't overflow the call stack.
+    fn topo(&self, roots: &[VariableDataIdx]) -> Vec<VariableDataIdx> {
         let mut topo = Vec::new();
-        let mut visited = HashSet::new();
-
-        fn build_topo(
-            v: VariableDataIdx,
-            topo: &mut Vec<VariableDataIdx>,
-            visited: &mut HashSet<VariableDataIdx>,
-            vars: &[VariableData],
-        ) {
-            if !visited.contains(&v) {
-                visited.insert(v);
-                for c in &vars[v].children {
-                    build_topo(*c, topo, visited, vars);
+        // (variable, index of the next child to visit)
+        let mut stack: Vec<(VariableDataIdx, usize)> = Vec::new();
+
+        let vars = self.vars.borrow();
+        let mut visited = self.visited.borrow_mut();
+        visited.resize(vars.len(), false);
+        let mut visit = |v: VariableDataIdx| !std::mem::replace(&mut visited[v], true);
+        for &root in roots {
+            if visit(root) {
+                stack.push((root, 0));
+            }
+            while let Some(top) = stack.last_mut() {
+                let (v, next) = *top;
+                if let Some(&c) = vars[v].children.get(next) {
+                    top.1 += 1;
+                    if visit(c) {
+                        stack.push((c, 0));
+                    }
+                } else {
+                    stack.pop();
+                    topo.push(v);
                 }
-                topo.push(v);
             }
         }
+        // only the visited entries are reset, the buffer is kept for the next call
+        for &v in &topo {
+            visited[v] = false;
+        }
+        topo
+    }
 
-        build_topo(idx, &mut topo, &mut visited, &self.vars.borrow());
-        self.vars.borrow_mut()[idx].grad = Some(1.0);
+    /// Backpropagate gradiants through the graph
+    fn backward(&self, idx: VariableDataIdx) {
+        self.backward_seeded(&[(idx, 1.0)]);
+    }
+
+    /// Backpropagate after removing the gradients of everything `idx` depends on
+    fn backward_replace(&self, idx: VariableDataIdx) {
+        let topo = self.topo(&[idx]);
+        {
+            let mut vars = self.vars.borrow_mut();
+            for v in topo {
+                vars[v].grad = None;
+                vars[v].grad_var = None;
+            }
+        }
+        self.backward(idx);
+    }
+
+    /// Backpropagate from several roots at once, each seeded with its own gradient.
+    /// Shared subgraphs are traversed only once, so their gradients aren't double counted.
+    fn backward_seeded(&self, seeds: &[(VariableDataIdx, f64)]) {
+        let roots: Vec<_> = seeds.iter().map(|&(idx, _)| idx).collect();
+        let topo = self.topo(&roots);
+        {
+            let mut vars = self.vars.borrow_mut();
+            for &(idx, grad) in seeds {
+                vars[idx].grad = Some(grad);
+            }
+        }
 
         // c = a + b => topo=vec![a,b,c] => rev() to start from c pushing the grads though the graph
         for v in topo.iter().rev() {
             self.backward_single(*v);
         }
     }
+
+    /// Backpropagate by recording the gradient computation as new variables of the graph, so the
+    /// gradients can be differentiated again. Sets both grad and grad_var of all visited variables,
+    /// overwriting earlier gradients instead of accumulating like `backward`.
+    fn backward_create_graph(&self, idx: VariableDataIdx) {
+        let topo = self.topo(&[idx]);
+        let mut grads: Vec<Option<VariableDataIdx>> = vec![None; self.len()];
+        grads[idx] = Some(self.variable(1.0).idx);
+
+        for &v in topo.iter().rev() {
+            let Some(out_grad) = grads[v] else {
+                continue;
+            };
+            let children = self.vars.borrow()[v].children.clone();
+            let child_grads = self.backward_op_graph(v, out_grad);
+            for (&c, grad) in children.iter().zip(child_grads) {
+                grads[c] = Some(match grads[c] {
+                    Some(acc) => self.add_op(acc, grad),
+                    None => grad,
+                });
+            }
+        }
+
+        let mut vars = self.vars.borrow_mut();
+        let mut owners = self.grad_var_owners.borrow_mut();
+        for v in topo {
+            if let Some(grad) = grads[v] {
+                vars[v].grad = Some(vars[grad].data);
+                if vars[v].grad_var.replace(grad).is_none() {
+                    owners.push(v);
+                }
+            }
+        }
+    }
+
+    /// Gradients of the children of `v` built from graph ops, the counterpart of `Op::backward`
+    fn backward_op_graph(
+        &self,
+        v: VariableDataIdx,
+        out_grad: VariableDataIdx,
+    ) -> Vec<VariableDataIdx> {
+        let (op, children, children_data, out_data) = {
+            let vars = self.vars.borrow();
+            let var = &vars[v];
+            let children_data: Vec<f64> = var.children.iter().map(|&c| vars[c].data).collect();
+            (var.op, var.children.clone(), children_data, var.data)
+        };
+        let var = |idx| Variable::new(idx, self);
+        let (g, out) = (var(out_grad), var(v));
+        let one = || self.variable(1.0);
+        let grads: Vec<Variable<'_>> = match op {
+            Op::Value => vec![],
+            Op::Add => vec![g, g],
+            Op::Mul => vec![g * var(children[1]), g * var(children[0])],
+            Op::AddScalar(_) => vec![g],
+            Op::MulScalar(s) => vec![g * s],
+            Op::Pow(exp) => vec![g * self.variable(exp) * var(children[0]).pow(exp - 1.0)],
+            Op::Tanh => vec![g * (one() - out * out)],
+            Op::Sigmoid => vec![g * out * (one() - out)],
+            Op::Softsign => vec![g * (one() + var(children[0]).abs()).pow(-2.0)],
+            Op::Exp => vec![g * out],
+            Op::Log => vec![g * var(children[0]).pow(-1.0)],
+            Op::LogClamped(eps) if children_data[0] > eps => vec![g * var(children[0]).pow(-1.0)],
+            Op::LogClamped(_) => vec![g * 0.0],
+            Op::Affine => {
+                let n = (children.len() - 1) / 2;
+                let (w, x) = children[1..].split_at(n);
+                std::iter::once(g)
+                    .chain(x.iter().map(|&x| g * var(x)))
+                    .chain(w.iter().map(|&w| g * var(w)))
+                    .collect()
+            }
+            // piecewise linear: the local derivative is a constant
+            Op::ReLU | Op::LeakyReLU(_) | Op::HardSigmoid | Op::Abs => {
+                let mut derivatives = Vec::new();
+                op.backward(&children_data, out_data, 1.0, &mut derivatives);
+                derivatives
+                    .into_iter()
+                    .map(|d| g * self.variable(d))
+                    .collect()
+            }
+        };
+        grads.into_iter().map(|g| g.idx).collect()
+    }
+
+    /// Backpropagate from `root` in chunks of `chunk` variables of the reverse topological order,
+    /// releasing every intermediate variable once its chunk is done: its gradient is spent and its
+    /// slot goes back to the free list, so a huge loss doesn't outlive its own backward pass.
+    /// Leaf gradients are identical to `backward`. `root` and all intermediate variables must not
+    /// be used afterwards, panics if a variable outside this pass still depends on one of them.
+    pub fn backprop_chunked(&self, root: Variable<'_>, chunk: usize) {
+        assert!(chunk > 0, "backprop_chunked: chunk must be positive");
+        assert!(
+            std::ptr::eq(self, root.graph),
+            "cannot backpropagate a variable of a different graph"
+        );
+        let topo = self.topo(&[root.idx]);
+        {
+            let mut vars = self.vars.borrow_mut();
+            let mut in_pass = self.visited.borrow_mut();
+            for &v in &topo {
+                in_pass[v] = !vars[v].children.is_empty();
+            }
+            let outside = vars.iter().enumerate().find_map(|(idx, var)| {
+                let c = var.children.iter().find(|&&c| in_pass[c])?;
+                (!in_pass[idx]).then_some((*c, idx))
+            });
+            for &v in &topo {
+                in_pass[v] = false;
+            }
+            if let Some((c, idx)) = outside {
+                panic!("backprop_chunked: variable {c} is still used by variable {idx}");
+            }
+            vars[root.idx].grad = Some(1.0);
+        }
+
+        let mut released = HashSet::new();
+        for nodes in topo.rchunks(chunk) {
+            for &v in nodes.iter().rev() {
+                self.backward_single(v);
+            }
+            // all consumers of these nodes come later in topo and are done
+            let mut vars = self.vars.borrow_mut();
+            let mut free = self.free.borrow_mut();
+            for &v in nodes {
+                if !vars[v].children.is_empty() {
+                    vars[v] = VariableData::new(0.0);
+                    free.push(v);
+                    released.insert(v);
+                }
+            }
+        }
+        self.reused
+            .borrow_mut()
+            .retain(|idx| !released.contains(idx));
+    }
+
+    /// Re-run the forward pass of all variables `root` depends on in topological order, refreshing
+    /// their cached data in place, e.g. after an optimizer changed parameters via `set_data`. Cheaper
+    /// than truncating and rebuilding the graph when only leaf values changed.
+    pub fn forward(&self, root: Variable<'_>) {
+        assert!(
+            std::ptr::eq(self, root.graph),
+            "cannot forward a variable of a different graph"
+        );
+        let topo = self.topo(&[root.idx]);
+        let mut vars = self.vars.borrow_mut();
+        let mut children_data = Vec::new();
+        for v in topo {
+            if let Op::Value = vars[v].op {
+                continue;
+            }
+            children_data.clear();
+            children_data.extend(vars[v].children.iter().map(|&c| vars[c].data));
+            vars[v].data = vars[v].op.forward(&children_data);
+        }
+    }
 }
 
 impl Default for Graph {
@@ -230,10 +1002,10 @@ impl Default for Graph {
 
 /// Convenience Variable on which operations like +, *, /, etc. are defined for a nice API.
 /// Can be cheaply copied, only holds index of data in graph/arena and a reference to the graph
-#[derive(Debug, Copy, Clone)]
+#[derive(Copy, Clone)]
 pub struct Variable<'a> {
-    idx: VariableDataIdx,
-    graph: &'a Graph,
+    pub(crate) idx: VariableDataIdx,
+    pub(crate) graph: &'a Graph,
 }
 
 impl<'a> Variable<'a> {
@@ -241,10 +1013,60 @@ impl<'a> Variable<'a> {
         Variable { idx, graph }
     }
 
+    /// Indices are only meaningful within one arena, combining variables of different graphs
+    /// would silently read unrelated data
+    fn assert_same_graph(self, other: Variable<'_>) {
+        assert!(
+            std::ptr::eq(self.graph, other.graph),
+            "cannot combine variables of different graphs"
+        );
+    }
+
+    /// Backpropagate from this variable. Gradients are added to the existing ones, so calling
+    /// backward on several microbatch losses without `zero_grad` in between accumulates their sum.
+    /// This holds as long as the losses only share leaves (e.g. parameters): an intermediate
+    /// variable that already carries a gradient would propagate it a second time.
     pub fn backward(self) {
         self.graph.backward(self.idx);
     }
 
+    /// Backward that also records the gradients as variables (see `grad_var`), so calling
+    /// backward on a gradient yields second order derivatives.
+    /// Unlike `backward` it overwrites the gradients of the visited variables, it doesn't accumulate.
+    pub fn backward_create_graph(self) {
+        self.graph.backward_create_graph(self.idx);
+    }
+
+    /// The gradient as a differentiable variable, set by `backward_create_graph`
+    pub fn grad_var(self) -> Option<Variable<'a>> {
+        self.graph
+            .grad_var(self.idx)
+            .map(|idx| Variable::new(idx, self.graph))
+    }
+
+    /// Backpropagate, replacing instead of accumulating: the gradients of all variables this one
+    /// depends on are removed first, so calling it twice gives the same result as calling it once
+    pub fn backward_replace(self) {
+        self.graph.backward_replace(self.idx);
+    }
+
+    /// Backpropagate and then truncate the graph to `checkpoint`, the usual end of a training step.
+    /// Gradients of variables below the checkpoint (e.g. parameters) are kept, this variable and
+    /// everything else above the checkpoint is removed and must not be used afterwards.
+    pub fn backward_and_free(self, checkpoint: usize) {
+        self.backward();
+        self.graph.truncate(checkpoint);
+    }
+
+    /// Backpropagate only if the value is finite, returns whether backward ran
+    pub fn backward_checked(self) -> bool {
+        if !self.data().is_finite() {
+            return false;
+        }
+        self.backward();
+        true
+    }
+
     pub fn data(self) -> f64 {
         self.graph.data(self.idx)
     }
@@ -257,20 +1079,69 @@ impl<'a> Variable<'a> {
         self.graph.set_data(self.idx, data);
     }
 
+    pub fn set_grad(self, grad: f64) {
+        self.graph.set_grad(self.idx, grad);
+    }
+
     pub fn zero_grad(self) {
         self.graph.zero_grad_single(self.idx);
     }
 
+    /// New leaf with the current data, gradients don't propagate past it (e.g. for target networks)
+    pub fn detach(self) -> Self {
+        self.graph.variable(self.data())
+    }
+
+    /// Frozen parameters (`false`) still receive gradients but are skipped by the optimizers
+    pub fn requires_grad(self) -> bool {
+        self.graph.requires_grad(self.idx)
+    }
+
+    pub fn set_requires_grad(self, requires_grad: bool) {
+        self.graph.set_requires_grad(self.idx, requires_grad);
+    }
+
     pub fn pow(self, exp: f64) -> Self {
         let idx = self.graph.pow_op(self.idx, exp);
         Variable::new(idx, self.graph)
     }
 
+    pub fn sqrt(self) -> Self {
+        self.pow(0.5)
+    }
+
     pub fn relu(self) -> Self {
         let idx = self.graph.relu_op(self.idx);
         Variable::new(idx, self.graph)
     }
 
+    pub fn leaky_relu(self, slope: f64) -> Self {
+        let idx = self.graph.leaky_relu_op(self.idx, slope);
+        Variable::new(idx, self.graph)
+    }
+
+    pub fn tanh(self) -> Self {
+        let idx = self.graph.tanh_op(self.idx);
+        Variable::new(idx, self.graph)
+    }
+
+    pub fn sigmoid(self) -> Self {
+        let idx = self.graph.sigmoid_op(self.idx);
+        Variable::new(idx, self.graph)
+    }
+
+    /// clamp(0.2 * x + 0.5, 0, 1)
+    pub fn hard_sigmoid(self) -> Self {
+        let idx = self.graph.hard_sigmoid_op(self.idx);
+        Variable::new(idx, self.graph)
+    }
+
+    /// x / (1 + |x|)
+    pub fn softsign(self) -> Self {
+        let idx = self.graph.softsign_op(self.idx);
+        Variable::new(idx, self.graph)
+    }
+
     pub fn exp(self) -> Self {
         let idx = self.graph.exp_op(self.idx);
         Variable::new(idx, self.graph)
@@ -280,11 +1151,23 @@ impl<'a> Variable<'a> {
         let idx = self.graph.log_op(self.idx);
         Variable::new(idx, self.graph)
     }
+
+    /// ln(max(x, eps)), stays finite for x <= 0 and has zero gradient in the clamped region
+    pub fn log_clamped(self, eps: f64) -> Self {
+        let idx = self.graph.log_clamped_op(self.idx, eps);
+        Variable::new(idx, self.graph)
+    }
+
+    pub fn abs(self) -> Self {
+        let idx = self.graph.abs_op(self.idx);
+        Variable::new(idx, self.graph)
+    }
 }
 
 impl<'a> Add for Variable<'a> {
     type Output = Variable<'a>;
     fn add(self, rhs: Self) -> Self {
+        self.assert_same_graph(rhs);
         let idx = self.graph.add_op(self.idx, rhs.idx);
         Variable::new(idx, self.graph)
     }
@@ -293,6 +1176,7 @@ impl<'a> Add for Variable<'a> {
 impl<'a> Sub for Variable<'a> {
     type Output = Variable<'a>;
     fn sub(self, rhs: Self) -> Self {
+        self.assert_same_graph(rhs);
         let idx = self.graph.sub_op(self.idx, rhs.idx);
         Variable::new(idx, self.graph)
     }
@@ -301,6 +1185,7 @@ impl<'a> Sub for Variable<'a> {
 impl<'a> Mul for Variable<'a> {
     type Output = Variable<'a>;
     fn mul(self, rhs: Self) -> Self {
+        self.assert_same_graph(rhs);
         let idx = self.graph.mul_op(self.idx, rhs.idx);
         Variable::new(idx, self.graph)
     }
@@ -309,11 +1194,78 @@ impl<'a> Mul for Variable<'a> {
 impl<'a> Div for Variable<'a> {
     type Output = Variable<'a>;
     fn div(self, rhs: Self) -> Self {
+        self.assert_same_graph(rhs);
         let idx = self.graph.div_op(self.idx, rhs.idx);
         Variable::new(idx, self.graph)
     }
 }
 
+// Scalar operands are folded into the op, no constant variables are created
+
+impl<'a> Add<f64> for Variable<'a> {
+    type Output = Variable<'a>;
+    fn add(self, rhs: f64) -> Self {
+        let idx = self.graph.add_scalar_op(self.idx, rhs);
+        Variable::new(idx, self.graph)
+    }
+}
+
+impl<'a> Sub<f64> for Variable<'a> {
+    type Output = Variable<'a>;
+    fn sub(self, rhs: f64) -> Self {
+        let idx = self.graph.add_scalar_op(self.idx, -rhs);
+        Variable::new(idx, self.graph)
+    }
+}
+
+impl<'a> Mul<f64> for Variable<'a> {
+    type Output = Variable<'a>;
+    fn mul(self, rhs: f64) -> Self {
+        let idx = self.graph.mul_scalar_op(self.idx, rhs);
+        Variable::new(idx, self.graph)
+    }
+}
+
+impl<'a> Div<f64> for Variable<'a> {
+    type Output = Variable<'a>;
+    fn div(self, rhs: f64) -> Self {
+        let idx = self.graph.mul_scalar_op(self.idx, rhs.recip());
+        Variable::new(idx, self.graph)
+    }
+}
+
+impl<'a> Add<Variable<'a>> for f64 {
+    type Output = Variable<'a>;
+    fn add(self, rhs: Variable<'a>) -> Variable<'a> {
+        rhs + self
+    }
+}
+
+impl<'a> Sub<Variable<'a>> for f64 {
+    type Output = Variable<'a>;
+    fn sub(self, rhs: Variable<'a>) -> Variable<'a> {
+        let neg = rhs.graph.mul_scalar_op(rhs.idx, -1.0);
+        let idx = rhs.graph.add_scalar_op(neg, self);
+        Variable::new(idx, rhs.graph)
+    }
+}
+
+impl<'a> Mul<Variable<'a>> for f64 {
+    type Output = Variable<'a>;
+    fn mul(self, rhs: Variable<'a>) -> Variable<'a> {
+        rhs * self
+    }
+}
+
+impl<'a> Div<Variable<'a>> for f64 {
+    type Output = Variable<'a>;
+    fn div(self, rhs: Variable<'a>) -> Variable<'a> {
+        let inv = rhs.graph.pow_op(rhs.idx, -1.0);
+        let idx = rhs.graph.mul_scalar_op(inv, self);
+        Variable::new(idx, rhs.graph)
+    }
+}
+
 impl<'a> Neg for Variable<'a> {
     type Output = Variable<'a>;
 
@@ -323,24 +1275,360 @@ impl<'a> Neg for Variable<'a> {
     }
 }
 
+// Compound assignment pushes the same op as the binary operator and rebinds self to its result
+
+impl AddAssign for Variable<'_> {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl AddAssign<f64> for Variable<'_> {
+    fn add_assign(&mut self, rhs: f64) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Variable<'_> {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl SubAssign<f64> for Variable<'_> {
+    fn sub_assign(&mut self, rhs: f64) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign for Variable<'_> {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+impl MulAssign<f64> for Variable<'_> {
+    fn mul_assign(&mut self, rhs: f64) {
+        *self = *self * rhs;
+    }
+}
+
+impl DivAssign for Variable<'_> {
+    fn div_assign(&mut self, rhs: Self) {
+        *self = *self / rhs;
+    }
+}
+
+impl DivAssign<f64> for Variable<'_> {
+    fn div_assign(&mut self, rhs: f64) {
+        *self = *self / rhs;
+    }
+}
+
+/// Balanced sum like `Graph::sum`. The graph is taken from the first element, so summing an empty
+/// iterator panics, use `Graph::sum` if it may be empty.
+impl<'a> std::iter::Sum for Variable<'a> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        let xs: Vec<_> = iter.collect();
+        let first = xs
+            .first()
+            .expect("cannot sum an empty iterator of variables");
+        first.graph.sum(&xs)
+    }
+}
+
+impl<'a, 'b> std::iter::Sum<&'b Variable<'a>> for Variable<'a> {
+    fn sum<I: Iterator<Item = &'b Variable<'a>>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
+
+/// Connects the outputs of an upstream graph to leaf inputs of a downstream graph, e.g. to split a
+/// large model across several arenas. Values are copied (detached) forward, gradients are bridged back.
+pub struct GraphBridge<'u, 'd> {
+    upstream: Vec<Variable<'u>>,
+    downstream: Vec<Variable<'d>>,
+}
+
+impl<'u, 'd> GraphBridge<'u, 'd> {
+    /// Copy the current values of the upstream outputs into new leaves of the downstream graph
+    pub fn new(upstream: &[Variable<'u>], downstream: &'d Graph) -> Self {
+        Self {
+            upstream: upstream.to_vec(),
+            downstream: upstream
+                .iter()
+                .map(|v| downstream.variable(v.data()))
+                .collect(),
+        }
+    }
+
+    /// The downstream leaves holding the bridged values
+    pub fn inputs(&self) -> &[Variable<'d>] {
+        &self.downstream
+    }
+
+    /// Call after backprop in the downstream graph: seeds the upstream outputs with the gradients
+    /// of the downstream leaves and backpropagates through the upstream graph
+    pub fn backward(&self) {
+        let Some(first) = self.upstream.first() else {
+            return;
+        };
+        let seeds: Vec<_> = self
+            .upstream
+            .iter()
+            .zip(&self.downstream)
+            .map(|(u, d)| (u.idx, d.grad().unwrap_or_default()))
+            .collect();
+        first.graph.backward_seeded(&seeds);
+    }
+}
+
+fn alloc_in(
+    vars: &mut Vec<VariableData>,
+    free: &mut Vec<VariableDataIdx>,
+    reused: &mut Vec<VariableDataIdx>,
+    var: VariableData,
+) -> VariableDataIdx {
+    match free.pop() {
+        Some(idx) => {
+            vars[idx] = var;
+            reused.push(idx);
+            idx
+        }
+        None => {
+            vars.push(var);
+            vars.len() - 1
+        }
+    }
+}
+
+/// A variable on a cycle of children, if any. Slots released by `free` can be reused by variables
+/// created after their children, so the arena isn't necessarily in topological order.
+fn find_cycle(vars: &[VariableData]) -> Option<VariableDataIdx> {
+    // 0: unvisited, 1: on the current path, 2: done
+    let mut state = vec![0u8; vars.len()];
+    let mut stack: Vec<(VariableDataIdx, usize)> = Vec::new();
+    for root in 0..vars.len() {
+        if state[root] != 0 {
+            continue;
+        }
+        state[root] = 1;
+        stack.push((root, 0));
+        while let Some(top) = stack.last_mut() {
+            let (v, next) = *top;
+            if let Some(&c) = vars[v].children.get(next) {
+                top.1 += 1;
+                match state[c] {
+                    0 => {
+                        state[c] = 1;
+                        stack.push((c, 0));
+                    }
+                    1 => return Some(c),
+                    _ => {}
+                }
+            } else {
+                state[v] = 2;
+                stack.pop();
+            }
+        }
+    }
+    None
+}
+
+/// Compare the backprop gradient of `f` at `x0` with a central finite difference,
+/// returns (analytic, numeric). Useful to validate the backward formula of new ops.
+pub fn grad_check(
+    f: impl for<'a> Fn(&'a Graph, Variable<'a>) -> Variable<'a>,
+    x0: f64,
+    eps: f64,
+) -> (f64, f64) {
+    let eval = |x: f64| {
+        let g = Graph::new();
+        f(&g, g.variable(x)).data()
+    };
+    let numeric = (eval(x0 + eps) - eval(x0 - eps)) / (2.0 * eps);
+
+    let g = Graph::new();
+    let x = g.variable(x0);
+    f(&g, x).backward();
+    (x.grad().unwrap_or_default(), numeric)
+}
+
+/// Determinant by gaussian elimination with partial pivoting
+fn determinant(mut m: Vec<Vec<f64>>) -> f64 {
+    let n = m.len();
+    let mut det = 1.0;
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))
+            .unwrap();
+        if m[pivot][col] == 0.0 {
+            return 0.0;
+        }
+        if pivot != col {
+            m.swap(pivot, col);
+            det = -det;
+        }
+        det *= m[col][col];
+        let (done, rest) = m.split_at_mut(col + 1);
+        let pivot_row = &done[col];
+        for row in rest {
+            let factor = row[col] / pivot_row[col];
+            for (r, p) in row.iter_mut().zip(pivot_row).skip(col) {
+                *r -= factor * p;
+            }
+        }
+    }
+    det
+}
+
+/// `Var(data=2.0, grad=Some(1.0))`, a precision like `{:.2}` applies to data and grad
+impl std::fmt::Display for Variable<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (data, grad) = (self.data(), self.grad());
+        match (f.precision(), grad) {
+            (Some(p), Some(grad)) => write!(f, "Var(data={data:.p$}, grad=Some({grad:.p$}))"),
+            (Some(p), None) => write!(f, "Var(data={data:.p$}, grad=None)"),
+            (None, _) => write!(f, "Var(data={data:?}, grad={grad:?})"),
+        }
+    }
+}
+
+/// Like `Display`, plus the index in the graph instead of the whole graph
+impl std::fmt::Debug for Variable<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Var(#{}, data={:?}, grad={:?})",
+            self.idx,
+            self.data(),
+            self.grad()
+        )
+    }
+}
+
+/// Invalid input to a loss function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LossError {
+    TargetOutOfRange { target: usize, num_classes: usize },
+}
+
+impl std::fmt::Display for LossError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LossError::TargetOutOfRange {
+                target,
+                num_classes,
+            } => write!(
+                f,
+                "target class {target} is out of range for {num_classes} classes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LossError {}
+
+/// Random number generator remembering its seed
+#[derive(Debug)]
+pub struct SeededRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+/// Draw from N(mean, std^2) with the Box-Muller transform, e.g. for noise or weight init
+pub(crate) fn sample_normal<R: Rng + ?Sized>(mean: f64, std: f64, rng: &mut R) -> f64 {
+    // u1 in (0, 1] to avoid ln(0)
+    let u1 = 1.0 - rng.random::<f64>();
+    let u2 = rng.random::<f64>();
+    mean + std * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+impl RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        self.rng.fill_bytes(dst)
+    }
+}
+
 // Internal types
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 enum Op {
     Value,
     Add,
     Mul,
+    AddScalar(f64),
+    MulScalar(f64),
     Pow(f64),
     ReLU,
+    LeakyReLU(f64),
+    Tanh,
+    Sigmoid,
+    HardSigmoid,
+    Softsign,
     Exp,
     Log,
+    LogClamped(f64),
+    Abs,
+    Affine, // children: [b, w_0..w_n, x_0..x_n]
 }
 
 impl Op {
+    fn name(&self) -> &'static str {
+        match self {
+            Op::Value => "value",
+            Op::Add => "add",
+            Op::Mul => "mul",
+            Op::AddScalar(_) => "add_scalar",
+            Op::MulScalar(_) => "mul_scalar",
+            Op::Pow(_) => "pow",
+            Op::ReLU => "relu",
+            Op::LeakyReLU(_) => "leaky_relu",
+            Op::Tanh => "tanh",
+            Op::Sigmoid => "sigmoid",
+            Op::HardSigmoid => "hard_sigmoid",
+            Op::Softsign => "softsign",
+            Op::Exp => "exp",
+            Op::Log => "log",
+            Op::LogClamped(_) => "log_clamped",
+            Op::Abs => "abs",
+            Op::Affine => "affine",
+        }
+    }
+
+    /// Whether `n` children are valid for this op
+    fn accepts_children(&self, n: usize) -> bool {
+        match self {
+            Op::Value => n == 0,
+            Op::Add | Op::Mul => n == 2,
+            Op::Affine => n % 2 == 1,
+            _ => n == 1,
+        }
+    }
+
     fn forward(&self, children_data: &[f64]) -> f64 {
         match self {
             Op::Add => children_data[0] + children_data[1],
             Op::Mul => children_data[0] * children_data[1],
+            Op::AddScalar(s) => children_data[0] + s,
+            Op::MulScalar(s) => children_data[0] * s,
             Op::Pow(exp) => children_data[0].powf(*exp),
             Op::ReLU => {
                 if children_data[0] > 0.0 {
@@ -349,31 +1637,97 @@ impl Op {
                     0.0
                 }
             }
+            Op::LeakyReLU(slope) => {
+                if children_data[0] > 0.0 {
+                    children_data[0]
+                } else {
+                    slope * children_data[0]
+                }
+            }
+            Op::Tanh => children_data[0].tanh(),
+            Op::Sigmoid => 1.0 / (1.0 + (-children_data[0]).exp()),
+            Op::HardSigmoid => (0.2 * children_data[0] + 0.5).clamp(0.0, 1.0),
+            Op::Softsign => children_data[0] / (1.0 + children_data[0].abs()),
             Op::Exp => children_data[0].exp(),
             Op::Log => children_data[0].ln(),
+            Op::LogClamped(eps) => children_data[0].max(*eps).ln(),
+            Op::Abs => children_data[0].abs(),
+            Op::Affine => {
+                let n = (children_data.len() - 1) / 2;
+                let (w, x) = children_data[1..].split_at(n);
+                w.iter()
+                    .zip(x)
+                    .fold(children_data[0], |acc, (w, x)| acc + w * x)
+            }
             Op::Value => unimplemented!(),
         }
     }
 
-    fn backward(&self, children_data: &[f64], out_data: f64, out_grad: f64) -> Vec<f64> {
+    /// Gradients of the children, written into `grads` so callers can reuse its allocation
+    fn backward(&self, children_data: &[f64], out_data: f64, out_grad: f64, grads: &mut Vec<f64>) {
+        grads.clear();
         match self {
-            Op::Add => vec![out_grad, out_grad],
-            Op::Mul => vec![children_data[1] * out_grad, children_data[0] * out_grad],
-            Op::Pow(exp) => vec![exp * children_data[0].powf(exp - 1.0) * out_grad],
-            Op::ReLU => vec![if out_data > 0.0 { out_grad } else { 0.0 }],
-            Op::Exp => vec![out_data * out_grad], // d/dx exp(x) = exp(x)
-            Op::Log => vec![out_grad / children_data[0]], // d/dx ln(x) = 1/x
-            Op::Value => vec![],
+            Op::Add => grads.extend([out_grad, out_grad]),
+            Op::Mul => grads.extend([children_data[1] * out_grad, children_data[0] * out_grad]),
+            Op::AddScalar(_) => grads.extend([out_grad]),
+            Op::MulScalar(s) => grads.extend([s * out_grad]),
+            Op::Pow(exp) => grads.extend([exp * children_data[0].powf(exp - 1.0) * out_grad]),
+            Op::ReLU => grads.extend([if out_data > 0.0 { out_grad } else { 0.0 }]),
+            Op::LeakyReLU(slope) => grads.extend([if children_data[0] > 0.0 {
+                out_grad
+            } else {
+                slope * out_grad
+            }]),
+            Op::Tanh => grads.extend([(1.0 - out_data * out_data) * out_grad]), // d/dx tanh(x) = 1 - tanh(x)^2
+            Op::Sigmoid => grads.extend([out_data * (1.0 - out_data) * out_grad]), // d/dx s(x) = s(x)(1 - s(x))
+            Op::HardSigmoid => grads.extend([if out_data > 0.0 && out_data < 1.0 {
+                0.2 * out_grad
+            } else {
+                0.0 // saturated
+            }]),
+            Op::Softsign => grads.extend([out_grad / (1.0 + children_data[0].abs()).powi(2)]), // d/dx = 1/(1+|x|)^2
+            Op::Exp => grads.extend([out_data * out_grad]), // d/dx exp(x) = exp(x)
+            Op::Log => grads.extend([out_grad / children_data[0]]), // d/dx ln(x) = 1/x
+            Op::LogClamped(eps) => grads.extend([if children_data[0] > *eps {
+                out_grad / children_data[0]
+            } else {
+                0.0 // clamped
+            }]),
+            Op::Abs => grads.extend([if children_data[0] > 0.0 {
+                out_grad
+            } else if children_data[0] < 0.0 {
+                -out_grad
+            } else {
+                0.0 // subgradient at 0
+            }]),
+            Op::Affine => {
+                let n = (children_data.len() - 1) / 2;
+                let (w, x) = children_data[1..].split_at(n);
+                grads.push(out_grad);
+                grads.extend(x.iter().map(|x| x * out_grad)); // d/dw_i = x_i
+                grads.extend(w.iter().map(|w| w * out_grad)); // d/dx_i = w_i
+            }
+            Op::Value => {}
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// Layout of `Graph::to_json`
+#[derive(Serialize, Deserialize)]
+struct GraphJson<'g> {
+    vars: Cow<'g, [VariableData]>,
+    free: Cow<'g, [VariableDataIdx]>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct VariableData {
     data: f64,
     grad: Option<f64>,
     children: Vec<VariableDataIdx>,
     op: Op,
+    requires_grad: bool,
+    #[serde(skip)]
+    grad_var: Option<VariableDataIdx>, // gradient as a variable, set by backward_create_graph
 }
 
 impl VariableData {
@@ -383,6 +1737,8 @@ impl VariableData {
             grad: None,
             children: Vec::new(),
             op: Op::Value,
+            requires_grad: true,
+            grad_var: None,
         }
     }
 }
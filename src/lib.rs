@@ -1,3 +1,7 @@
+pub mod data;
 pub mod graph;
+pub mod metrics;
 pub mod nn;
 pub mod optim;
+pub mod tensor;
+pub mod train;
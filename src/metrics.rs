@@ -0,0 +1,96 @@
+use crate::graph::Variable;
+
+/// Fraction of parameters whose gradient magnitude is below `threshold`, missing gradients count as zero
+pub fn gradient_sparsity(params: &[Variable<'_>], threshold: f64) -> f64 {
+    if params.is_empty() {
+        return 0.0;
+    }
+    let sparse = params
+        .iter()
+        .filter(|p| p.grad().unwrap_or_default().abs() < threshold)
+        .count();
+    sparse as f64 / params.len() as f64
+}
+
+/// Index of the largest logit, e.g. the predicted class. NaN entries are never picked unless all
+/// entries are NaN, then it's 0. Ties go to the first index.
+pub fn argmax(logits: &[Variable<'_>]) -> usize {
+    assert!(!logits.is_empty(), "argmax of an empty slice");
+    let mut best: Option<(usize, f64)> = None;
+    for (i, v) in logits.iter().enumerate() {
+        let x = v.data();
+        if !x.is_nan() && best.is_none_or(|(_, b)| x > b) {
+            best = Some((i, x));
+        }
+    }
+    best.map_or(0, |(i, _)| i)
+}
+
+/// Whether the largest logit is the `target` class
+pub fn accuracy(logits: &[Variable<'_>], target: usize) -> bool {
+    !logits.is_empty() && argmax(logits) == target
+}
+
+/// Fraction of predictions matching their targets
+pub fn accuracy_rate(predictions: &[usize], targets: &[usize]) -> f64 {
+    assert_eq!(
+        predictions.len(),
+        targets.len(),
+        "predictions and targets differ in length"
+    );
+    if predictions.is_empty() {
+        return 0.0;
+    }
+    let correct = predictions
+        .iter()
+        .zip(targets)
+        .filter(|(p, t)| p == t)
+        .count();
+    correct as f64 / predictions.len() as f64
+}
+
+/// Count of true class `i` predicted as class `j` at `[i][j]`
+pub fn confusion_matrix(
+    predictions: &[usize],
+    targets: &[usize],
+    num_classes: usize,
+) -> Vec<Vec<usize>> {
+    assert_eq!(
+        predictions.len(),
+        targets.len(),
+        "predictions and targets differ in length"
+    );
+    let mut matrix = vec![vec![0; num_classes]; num_classes];
+    for (&p, &t) in predictions.iter().zip(targets) {
+        assert!(
+            p < num_classes && t < num_classes,
+            "class out of range for {num_classes} classes"
+        );
+        matrix[t][p] += 1;
+    }
+    matrix
+}
+
+/// Render a confusion matrix as a table, rows are true classes and columns predictions
+pub fn format_confusion_matrix(matrix: &[Vec<usize>]) -> String {
+    let width = matrix
+        .iter()
+        .flatten()
+        .chain([&matrix.len()])
+        .map(|n| n.to_string().len())
+        .max()
+        .unwrap_or(1);
+    let mut out = format!("{:>width$} |", "");
+    for j in 0..matrix.len() {
+        out += &format!(" {j:>width$}");
+    }
+    out += &format!("\n{}\n", "-".repeat(out.len()));
+    for (i, row) in matrix.iter().enumerate() {
+        out += &format!("{i:>width$} |");
+        for n in row {
+            out += &format!(" {n:>width$}");
+        }
+        out += "\n";
+    }
+    out
+}
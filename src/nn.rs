@@ -1,34 +1,216 @@
-use crate::graph::{Graph, Variable};
+use std::{
+    cell::{RefCell, RefMut},
+    fs, io,
+    path::Path,
+};
+
+use rand::{Rng, RngCore, SeedableRng, rngs::StdRng};
+
+use crate::graph::{Graph, Variable, sample_normal};
+
+/// Nonlinearity applied to the output of a neuron
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Activation {
+    #[default]
+    None,
+    ReLU,
+    Tanh,
+    Sigmoid,
+    LeakyReLU(f64),
+}
+
+impl Activation {
+    /// `true` maps to ReLU, `false` to no activation
+    pub fn from_nonlin(nonlin: bool) -> Self {
+        if nonlin {
+            Activation::ReLU
+        } else {
+            Activation::None
+        }
+    }
+
+    pub fn apply<'a>(&self, x: Variable<'a>) -> Variable<'a> {
+        match self {
+            Activation::None => x,
+            Activation::ReLU => x.relu(),
+            Activation::Tanh => x.tanh(),
+            Activation::Sigmoid => x.sigmoid(),
+            Activation::LeakyReLU(slope) => x.leaky_relu(*slope),
+        }
+    }
+
+    /// Same as `apply` on plain values
+    fn apply_value(&self, x: f64) -> f64 {
+        match self {
+            Activation::None => x,
+            Activation::ReLU => x.max(0.0),
+            Activation::Tanh => x.tanh(),
+            Activation::Sigmoid => 1.0 / (1.0 + (-x).exp()),
+            Activation::LeakyReLU(slope) => {
+                if x > 0.0 {
+                    x
+                } else {
+                    slope * x
+                }
+            }
+        }
+    }
+}
+
+/// Weight initialization strategy
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Init {
+    /// Uniform in [-1, 1]
+    Uniform,
+    /// Glorot uniform with variance 2 / (fan_in + fan_out), suited for tanh/sigmoid networks
+    Xavier,
+    /// He uniform with variance 2 / fan_in, suited for ReLU networks
+    #[default]
+    He,
+    /// Normal(mean, std)
+    Normal(f64, f64),
+    Constant(f64),
+}
+
+impl Init {
+    /// Draw a single weight of a layer with the given fan-in and fan-out
+    pub fn sample<R: Rng + ?Sized>(&self, fan_in: usize, fan_out: usize, rng: &mut R) -> f64 {
+        let mut uniform = |bound: f64| (rng.random::<f64>() * 2. - 1.) * bound;
+        match *self {
+            Init::Uniform => uniform(1.0),
+            // uniform in [-b, b] has variance b^2 / 3
+            Init::Xavier => uniform((6.0 / (fan_in + fan_out) as f64).sqrt()),
+            Init::He => uniform((6.0 / fan_in as f64).sqrt()),
+            Init::Normal(mean, std) => sample_normal(mean, std, rng),
+            Init::Constant(c) => c,
+        }
+    }
+}
+
+fn init_weights<'a, R: Rng + ?Sized>(
+    g: &'a Graph,
+    nin: i16,
+    fan_out: i16,
+    init: Init,
+    rng: &mut R,
+) -> Vec<Variable<'a>> {
+    (0..nin)
+        .map(|_| g.variable(init.sample(nin as usize, fan_out as usize, rng)))
+        .collect()
+}
+
+fn set_requires_grad(params: &[Variable<'_>], requires_grad: bool) {
+    for p in params {
+        p.set_requires_grad(requires_grad);
+    }
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Eigenvalues of a symmetric matrix with the cyclic Jacobi method
+fn symmetric_eigenvalues(mut a: Vec<Vec<f64>>) -> Vec<f64> {
+    let n = a.len();
+    let norm: f64 = a.iter().flatten().map(|x| x * x).sum();
+    for _ in 0..100 {
+        let off: f64 = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i][j] * a[i][j])
+            .sum();
+        if off <= 1e-24 * norm {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+                // rotation that zeroes a[p][q]
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut() {
+                    let (kp, kq) = (row[p], row[q]);
+                    row[p] = c * kp - s * kq;
+                    row[q] = s * kp + c * kq;
+                }
+                let (head, tail) = a.split_at_mut(q);
+                for (pk, qk) in head[p].iter_mut().zip(tail[0].iter_mut()) {
+                    (*pk, *qk) = (c * *pk - s * *qk, s * *pk + c * *qk);
+                }
+            }
+        }
+    }
+    (0..n).map(|i| a[i][i]).collect()
+}
 
 pub struct Neuron<'a> {
     w: Vec<Variable<'a>>,
     b: Variable<'a>,
-    nonlin: bool,
+    activation: Activation,
+    checkpointing: bool,
 }
 
 impl<'a> Neuron<'a> {
     pub fn new(g: &'a Graph, nin: i16, nonlin: bool) -> Self {
-        // He initialization: scale = sqrt(2 / fan_in)
-        let scale = (2.0 / nin as f64).sqrt();
-        let w = (0..nin)
-            .map(|_| g.variable((rand::random::<f64>() * 2. - 1.) * scale))
-            .collect();
+        Self::with_activation(g, nin, Activation::from_nonlin(nonlin))
+    }
+
+    pub fn with_activation(g: &'a Graph, nin: i16, activation: Activation) -> Self {
+        Self::with_init(g, nin, activation, Init::He)
+    }
+
+    pub fn with_init(g: &'a Graph, nin: i16, activation: Activation, init: Init) -> Self {
+        Self::with_rng(g, nin, activation, init, &mut rand::rng())
+    }
+
+    /// Weights are drawn from `rng`, pass a seeded rng for reproducible runs
+    pub fn with_rng<R: Rng + ?Sized>(
+        g: &'a Graph,
+        nin: i16,
+        activation: Activation,
+        init: Init,
+        rng: &mut R,
+    ) -> Self {
+        Self::build(g, nin, 1, activation, init, rng)
+    }
+
+    /// `fan_out` is the size of the layer the neuron belongs to
+    fn build<R: Rng + ?Sized>(
+        g: &'a Graph,
+        nin: i16,
+        fan_out: i16,
+        activation: Activation,
+        init: Init,
+        rng: &mut R,
+    ) -> Self {
         Self {
-            w,
+            w: init_weights(g, nin, fan_out, init, rng),
             b: g.variable(0.0),
-            nonlin,
+            activation,
+            checkpointing: false,
         }
     }
 
+    /// With checkpointing the forward pass doesn't keep the intermediate product nodes in the arena,
+    /// they are recomputed during backward (see `Graph::affine`)
+    pub fn set_checkpointing(&mut self, checkpointing: bool) {
+        self.checkpointing = checkpointing;
+    }
+
     pub fn forward(&self, x: &[Variable<'a>]) -> Variable<'a> {
+        if self.checkpointing {
+            let s = self.b.graph.affine(&self.w, x, self.b);
+            return self.activation.apply(s);
+        }
+
         let mut s = self.b;
         for (&wi, &xi) in self.w.iter().zip(x) {
-            s = s + wi * xi;
+            s += wi * xi;
         }
-        if self.nonlin {
-            s = s.relu()
-        }
-        s
+        self.activation.apply(s)
     }
 
     pub fn parameters(&self) -> Vec<Variable<'a>> {
@@ -44,7 +226,35 @@ pub struct Layer<'a> {
 
 impl<'a> Layer<'a> {
     pub fn new(g: &'a Graph, nin: i16, nout: i16, nonlin: bool) -> Self {
-        let neurons = (0..nout).map(|_| Neuron::new(g, nin, nonlin)).collect();
+        Self::with_activation(g, nin, nout, Activation::from_nonlin(nonlin))
+    }
+
+    pub fn with_activation(g: &'a Graph, nin: i16, nout: i16, activation: Activation) -> Self {
+        Self::with_init(g, nin, nout, activation, Init::He)
+    }
+
+    pub fn with_init(
+        g: &'a Graph,
+        nin: i16,
+        nout: i16,
+        activation: Activation,
+        init: Init,
+    ) -> Self {
+        Self::with_rng(g, nin, nout, activation, init, &mut rand::rng())
+    }
+
+    /// Weights are drawn from `rng`, pass a seeded rng for reproducible runs
+    pub fn with_rng<R: Rng + ?Sized>(
+        g: &'a Graph,
+        nin: i16,
+        nout: i16,
+        activation: Activation,
+        init: Init,
+        rng: &mut R,
+    ) -> Self {
+        let neurons = (0..nout)
+            .map(|_| Neuron::build(g, nin, nout, activation, init, rng))
+            .collect();
         Self { neurons }
     }
 
@@ -55,6 +265,319 @@ impl<'a> Layer<'a> {
     pub fn parameters(&self) -> Vec<Variable<'a>> {
         self.neurons.iter().flat_map(|n| n.parameters()).collect()
     }
+
+    /// (inputs, outputs)
+    fn dims(&self) -> (usize, usize) {
+        let nin = self.neurons.first().map_or(0, |n| n.w.len());
+        (nin, self.neurons.len())
+    }
+
+    /// Effective rank exp(H(p)) of the weight matrix, where p are the singular values normalized
+    /// to sum to one. Ranges from 1 (all energy in one direction) to min(nin, nout).
+    pub fn effective_rank(&self) -> f64 {
+        let w: Vec<Vec<f64>> = self
+            .neurons
+            .iter()
+            .map(|n| n.w.iter().map(|w| w.data()).collect())
+            .collect();
+        let (nin, nout) = self.dims();
+        // eigenvalues of the smaller gram matrix are the squared singular values
+        let gram: Vec<Vec<f64>> = if nout <= nin {
+            (0..nout)
+                .map(|i| (0..nout).map(|j| dot(&w[i], &w[j])).collect())
+                .collect()
+        } else {
+            let col = |j: usize| w.iter().map(|row| row[j]).collect::<Vec<_>>();
+            let cols: Vec<_> = (0..nin).map(col).collect();
+            (0..nin)
+                .map(|i| (0..nin).map(|j| dot(&cols[i], &cols[j])).collect())
+                .collect()
+        };
+        let singular: Vec<f64> = symmetric_eigenvalues(gram)
+            .into_iter()
+            .map(|l| l.max(0.0).sqrt())
+            .collect();
+        let total: f64 = singular.iter().sum();
+        if total == 0.0 {
+            return 0.0;
+        }
+        let entropy: f64 = singular
+            .iter()
+            .map(|s| s / total)
+            .filter(|&p| p > 0.0)
+            .map(|p| -p * p.ln())
+            .sum();
+        entropy.exp()
+    }
+
+    /// Exclude all parameters of the layer from optimizer updates
+    pub fn freeze(&self) {
+        set_requires_grad(&self.parameters(), false);
+    }
+
+    pub fn unfreeze(&self) {
+        set_requires_grad(&self.parameters(), true);
+    }
+}
+
+/// Affine map `W x (+ b)` without activation, the bias can be disabled for pure projections
+pub struct Linear<'a> {
+    w: Vec<Vec<Variable<'a>>>, // one row of nin weights per output
+    b: Option<Vec<Variable<'a>>>,
+}
+
+impl<'a> Linear<'a> {
+    pub fn new(g: &'a Graph, nin: i16, nout: i16, bias: bool) -> Self {
+        let w = (0..nout)
+            .map(|_| init_weights(g, nin, nout, Init::He, &mut rand::rng()))
+            .collect();
+        let b = bias.then(|| (0..nout).map(|_| g.variable(0.0)).collect());
+        Self { w, b }
+    }
+
+    pub fn forward(&self, x: &[Variable<'a>]) -> Vec<Variable<'a>> {
+        self.w
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let mut products = row.iter().zip(x).map(|(&wi, &xi)| wi * xi);
+                let first = match &self.b {
+                    Some(b) => b[i],
+                    None => products.next().expect("Linear needs at least one input"),
+                };
+                products.fold(first, |acc, p| acc + p)
+            })
+            .collect()
+    }
+
+    pub fn parameters(&self) -> Vec<Variable<'a>> {
+        let mut params: Vec<_> = self.w.iter().flatten().copied().collect();
+        if let Some(b) = &self.b {
+            params.extend(b);
+        }
+        params
+    }
+}
+
+/// Inverted dropout: in training mode every input is kept with probability 1 - p and scaled by
+/// 1 / (1 - p), in eval mode it's the identity. The mask is built from constant multiplier variables,
+/// so gradients only flow through the kept units.
+pub struct Dropout {
+    p: f64,
+    training: bool,
+    rng: Option<RefCell<StdRng>>, // None: draw from the graph's rng
+}
+
+impl Dropout {
+    /// Starts in training mode, the seed makes the masks reproducible
+    pub fn new(p: f64, seed: u64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&p),
+            "dropout probability must be in [0, 1)"
+        );
+        Self {
+            p,
+            training: true,
+            rng: Some(RefCell::new(StdRng::seed_from_u64(seed))),
+        }
+    }
+
+    /// Starts in training mode, the masks are drawn from the rng of the graph passed to `forward`
+    pub fn from_graph_rng(p: f64) -> Self {
+        Self {
+            rng: None,
+            ..Self::new(p, 0)
+        }
+    }
+
+    pub fn train(&mut self) {
+        self.training = true;
+    }
+
+    pub fn eval(&mut self) {
+        self.training = false;
+    }
+
+    pub fn is_training(&self) -> bool {
+        self.training
+    }
+
+    pub fn forward<'a>(&self, g: &'a Graph, x: &[Variable<'a>]) -> Vec<Variable<'a>> {
+        if !self.training {
+            return x.to_vec();
+        }
+        let scale = 1.0 / (1.0 - self.p);
+        let mut rng: RefMut<'_, dyn RngCore> = match &self.rng {
+            Some(rng) => rng.borrow_mut(),
+            None => g.rng(),
+        };
+        x.iter()
+            .map(|&xi| {
+                let keep = rng.random::<f64>() >= self.p;
+                xi * g.variable(if keep { scale } else { 0.0 })
+            })
+            .collect()
+    }
+}
+
+/// Layer normalization (x - mean) / sqrt(var + eps) over a feature vector,
+/// followed by a learnable per-feature gain (init 1) and bias (init 0)
+pub struct LayerNorm<'a> {
+    gain: Vec<Variable<'a>>,
+    bias: Vec<Variable<'a>>,
+    eps: f64,
+}
+
+impl<'a> LayerNorm<'a> {
+    pub fn new(g: &'a Graph, n: usize) -> Self {
+        Self {
+            gain: (0..n).map(|_| g.variable(1.0)).collect(),
+            bias: (0..n).map(|_| g.variable(0.0)).collect(),
+            eps: 1e-5,
+        }
+    }
+
+    pub fn forward(&self, x: &[Variable<'a>]) -> Vec<Variable<'a>> {
+        assert_eq!(
+            x.len(),
+            self.gain.len(),
+            "LayerNorm: wrong number of features"
+        );
+        let g = self.gain[0].graph;
+
+        let mean = g.mean(x);
+        let centered: Vec<_> = x.iter().map(|&xi| xi - mean).collect();
+        let sq: Vec<_> = centered.iter().map(|c| c.pow(2.0)).collect();
+        let std = (g.mean(&sq) + self.eps).sqrt();
+
+        centered
+            .iter()
+            .zip(self.gain.iter().zip(&self.bias))
+            .map(|(&c, (&gain, &bias))| c / std * gain + bias)
+            .collect()
+    }
+
+    pub fn parameters(&self) -> Vec<Variable<'a>> {
+        self.gain.iter().chain(&self.bias).copied().collect()
+    }
+}
+
+/// Batch normalization of every feature across a batch, followed by a learnable per-feature
+/// scale (init 1) and shift (init 0). Training mode normalizes with the batch statistics (gradients
+/// flow through them) and updates running averages, eval mode normalizes with the running averages.
+pub struct BatchNorm1d<'a> {
+    scale: Vec<Variable<'a>>,
+    shift: Vec<Variable<'a>>,
+    eps: f64,
+    momentum: f64,
+    running_mean: RefCell<Vec<f64>>,
+    running_var: RefCell<Vec<f64>>,
+    training: bool,
+}
+
+impl<'a> BatchNorm1d<'a> {
+    /// Starts in training mode
+    pub fn new(g: &'a Graph, num_features: usize) -> Self {
+        Self {
+            scale: (0..num_features).map(|_| g.variable(1.0)).collect(),
+            shift: (0..num_features).map(|_| g.variable(0.0)).collect(),
+            eps: 1e-5,
+            momentum: 0.1,
+            running_mean: RefCell::new(vec![0.0; num_features]),
+            running_var: RefCell::new(vec![1.0; num_features]),
+            training: true,
+        }
+    }
+
+    pub fn train(&mut self) {
+        self.training = true;
+    }
+
+    pub fn eval(&mut self) {
+        self.training = false;
+    }
+
+    pub fn running_mean(&self) -> Vec<f64> {
+        self.running_mean.borrow().clone()
+    }
+
+    pub fn running_var(&self) -> Vec<f64> {
+        self.running_var.borrow().clone()
+    }
+
+    /// Normalize a batch of samples, each with `num_features` values
+    pub fn forward(&self, batch: &[Vec<Variable<'a>>]) -> Vec<Vec<Variable<'a>>> {
+        let g = self.scale[0].graph;
+        let mut out = vec![Vec::with_capacity(self.scale.len()); batch.len()];
+
+        for f in 0..self.scale.len() {
+            let column: Vec<_> = batch.iter().map(|sample| sample[f]).collect();
+            let (mean, std) = if self.training {
+                let mean = g.mean(&column);
+                let sq: Vec<_> = column.iter().map(|&x| (x - mean).pow(2.0)).collect();
+                let var = g.mean(&sq);
+
+                let m = self.momentum;
+                let mut running_mean = self.running_mean.borrow_mut();
+                let mut running_var = self.running_var.borrow_mut();
+                running_mean[f] = (1.0 - m) * running_mean[f] + m * mean.data();
+                running_var[f] = (1.0 - m) * running_var[f] + m * var.data();
+
+                (mean, (var + self.eps).sqrt())
+            } else {
+                let mean = g.variable(self.running_mean.borrow()[f]);
+                let std = g.variable((self.running_var.borrow()[f] + self.eps).sqrt());
+                (mean, std)
+            };
+
+            for (sample, &x) in out.iter_mut().zip(&column) {
+                sample.push((x - mean) / std * self.scale[f] + self.shift[f]);
+            }
+        }
+        out
+    }
+
+    pub fn parameters(&self) -> Vec<Variable<'a>> {
+        self.scale.iter().chain(&self.shift).copied().collect()
+    }
+}
+
+/// Lookup table of `num_embeddings` trainable vectors of size `dim`.
+/// Lookups return the table variables themselves, so only used rows receive gradients.
+pub struct Embedding<'a> {
+    table: Vec<Vec<Variable<'a>>>,
+}
+
+impl<'a> Embedding<'a> {
+    pub fn new(g: &'a Graph, num_embeddings: usize, dim: usize) -> Self {
+        let mut rng = rand::rng();
+        let table = (0..num_embeddings)
+            .map(|_| {
+                (0..dim)
+                    .map(|_| g.variable(Init::Uniform.sample(1, dim, &mut rng)))
+                    .collect()
+            })
+            .collect();
+        Self { table }
+    }
+
+    pub fn forward(&self, indices: &[usize]) -> Vec<Vec<Variable<'a>>> {
+        indices
+            .iter()
+            .map(|&i| {
+                assert!(
+                    i < self.table.len(),
+                    "embedding index {i} out of range for {} embeddings",
+                    self.table.len()
+                );
+                self.table[i].clone()
+            })
+            .collect()
+    }
+
+    pub fn parameters(&self) -> Vec<Variable<'a>> {
+        self.table.iter().flatten().copied().collect()
+    }
 }
 
 pub struct MLP<'a> {
@@ -62,16 +585,114 @@ pub struct MLP<'a> {
 }
 
 impl<'a> MLP<'a> {
+    /// ReLU on all hidden layers, linear output layer
     pub fn new(g: &'a Graph, nin: i16, nouts: Vec<i16>) -> Self {
-        let n = nouts.len();
-        let mut layers = Vec::new();
-
-        layers.push(Layer::new(g, nin, nouts[0], n > 1)); // nonlin if not last
+        let activations = Self::default_activations(nouts.len());
+        Self::with_activations(g, nin, nouts, &activations)
+    }
 
-        for i in 1..n {
-            let is_last = i == n - 1;
-            layers.push(Layer::new(g, nouts[i - 1], nouts[i], !is_last));
+    /// Same as `new`, but the output layer's initial weights are multiplied by `output_scale`.
+    /// A small scale starts classifiers with logits near zero, i.e. a loss near ln(num_classes).
+    pub fn with_output_scale(g: &'a Graph, nin: i16, nouts: Vec<i16>, output_scale: f64) -> Self {
+        let mlp = Self::new(g, nin, nouts);
+        if let Some(last) = mlp.layers.last() {
+            for w in last.neurons.iter().flat_map(|n| &n.w) {
+                w.set_data(w.data() * output_scale);
+            }
         }
+        mlp
+    }
+
+    /// Same as `new`, but identical seeds produce identical initial weights
+    pub fn seeded(g: &'a Graph, nin: i16, nouts: Vec<i16>, seed: u64) -> Self {
+        let activations = Self::default_activations(nouts.len());
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self::with_rng(g, nin, nouts, &activations, Init::He, &mut rng)
+    }
+
+    /// Same architecture as `new` built around existing variables in the order of `parameters`,
+    /// e.g. to evaluate a copy of a model on another graph
+    pub fn from_parameters(nin: i16, nouts: Vec<i16>, params: &[Variable<'a>]) -> Self {
+        let activations = Self::default_activations(nouts.len());
+        let nins = std::iter::once(nin as usize).chain(nouts.iter().map(|&n| n as usize));
+        let mut params = params.iter().copied();
+        let layers = nins
+            .zip(&nouts)
+            .zip(activations)
+            .map(|((nin, &nout), activation)| {
+                let neurons = (0..nout)
+                    .map(|_| {
+                        let w: Vec<_> = params.by_ref().take(nin).collect();
+                        let b = params.next();
+                        assert!(
+                            w.len() == nin && b.is_some(),
+                            "MLP::from_parameters: too few parameters for the architecture"
+                        );
+                        Neuron {
+                            w,
+                            b: b.unwrap(),
+                            activation,
+                            checkpointing: false,
+                        }
+                    })
+                    .collect();
+                Layer { neurons }
+            })
+            .collect();
+        assert!(
+            params.next().is_none(),
+            "MLP::from_parameters: too many parameters for the architecture"
+        );
+        Self { layers }
+    }
+
+    fn default_activations(n: usize) -> Vec<Activation> {
+        (0..n)
+            .map(|i| Activation::from_nonlin(i != n - 1)) // nonlin if not last
+            .collect()
+    }
+
+    /// One activation per layer
+    pub fn with_activations(
+        g: &'a Graph,
+        nin: i16,
+        nouts: Vec<i16>,
+        activations: &[Activation],
+    ) -> Self {
+        Self::with_init(g, nin, nouts, activations, Init::He)
+    }
+
+    /// One activation per layer, all weights initialized with `init`
+    pub fn with_init(
+        g: &'a Graph,
+        nin: i16,
+        nouts: Vec<i16>,
+        activations: &[Activation],
+        init: Init,
+    ) -> Self {
+        Self::with_rng(g, nin, nouts, activations, init, &mut rand::rng())
+    }
+
+    /// Weights are drawn from `rng`, pass a seeded rng for reproducible runs
+    pub fn with_rng<R: Rng + ?Sized>(
+        g: &'a Graph,
+        nin: i16,
+        nouts: Vec<i16>,
+        activations: &[Activation],
+        init: Init,
+        rng: &mut R,
+    ) -> Self {
+        assert_eq!(
+            nouts.len(),
+            activations.len(),
+            "MLP needs exactly one activation per layer"
+        );
+        let nins = std::iter::once(nin).chain(nouts.iter().copied());
+        let layers = nins
+            .zip(&nouts)
+            .zip(activations)
+            .map(|((nin, &nout), &activation)| Layer::with_rng(g, nin, nout, activation, init, rng))
+            .collect();
 
         Self { layers }
     }
@@ -84,7 +705,360 @@ impl<'a> MLP<'a> {
         out
     }
 
+    /// Forward every sample of a batch, returns the outputs per sample
+    pub fn forward_batch(&self, inputs: &[Vec<Variable<'a>>]) -> Vec<Vec<Variable<'a>>> {
+        inputs.iter().map(|x| self.forward(x)).collect()
+    }
+
     pub fn parameters(&self) -> Vec<Variable<'a>> {
         self.layers.iter().flat_map(|l| l.parameters()).collect()
     }
+
+    pub fn num_parameters(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|l| {
+                let (nin, nout) = l.dims();
+                (nin + 1) * nout
+            })
+            .sum()
+    }
+
+    /// Table of the layer dimensions and parameter counts, similar to Keras' `model.summary()`
+    pub fn summary(&self) -> String {
+        let mut out = format!(
+            "{:<8}{:>8}{:>8}{:>10}  Activation\n",
+            "Layer", "In", "Out", "Params"
+        );
+        for (i, layer) in self.layers.iter().enumerate() {
+            let (nin, nout) = layer.dims();
+            let activation = layer
+                .neurons
+                .first()
+                .map_or(Activation::None, |n| n.activation);
+            out += &format!(
+                "{:<8}{nin:>8}{nout:>8}{:>10}  {activation:?}\n",
+                i,
+                (nin + 1) * nout
+            );
+        }
+        out += &format!("Total params: {}\n", self.num_parameters());
+        out
+    }
+
+    /// Store all parameter values as a JSON array
+    pub fn save_weights(&self, path: &Path) -> io::Result<()> {
+        let values: Vec<f64> = self.parameters().iter().map(|p| p.data()).collect();
+        fs::write(path, serde_json::to_string(&values)?)
+    }
+
+    /// Load parameter values stored by `save_weights`, the architecture must match
+    pub fn load_weights(&self, path: &Path) -> io::Result<()> {
+        let values: Vec<f64> = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let params = self.parameters();
+        if values.len() != params.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "expected {} parameters, file has {}",
+                    params.len(),
+                    values.len()
+                ),
+            ));
+        }
+        for (p, v) in params.iter().zip(values) {
+            p.set_data(v);
+        }
+        Ok(())
+    }
+
+    /// Exclude all parameters from optimizer updates
+    pub fn freeze(&self) {
+        self.layers.iter().for_each(Layer::freeze);
+    }
+
+    pub fn unfreeze(&self) {
+        self.layers.iter().for_each(Layer::unfreeze);
+    }
+
+    /// Freeze a single layer, e.g. everything but the head for fine-tuning
+    pub fn freeze_layer(&self, layer: usize) {
+        self.layers[layer].freeze();
+    }
+
+    pub fn unfreeze_layer(&self, layer: usize) {
+        self.layers[layer].unfreeze();
+    }
+
+    /// Mean and (population) standard deviation of every layer's outputs over a batch of inputs.
+    /// The graph is truncated back to its current length afterwards.
+    pub fn activation_stats(&self, g: &'a Graph, batch: &[Vec<f64>]) -> Vec<(f64, f64)> {
+        let checkpoint = g.len();
+        let mut outputs: Vec<Vec<f64>> = vec![Vec::new(); self.layers.len()];
+
+        for input in batch {
+            let mut out = g.variables(input);
+            for (layer, acts) in self.layers.iter().zip(outputs.iter_mut()) {
+                out = layer.forward(&out);
+                acts.extend(out.iter().map(|v| v.data()));
+            }
+            g.truncate(checkpoint);
+        }
+
+        outputs
+            .iter()
+            .map(|acts| {
+                let n = acts.len() as f64;
+                let mean = acts.iter().sum::<f64>() / n;
+                let var = acts.iter().map(|a| (a - mean).powi(2)).sum::<f64>() / n;
+                (mean, var.sqrt())
+            })
+            .collect()
+    }
+
+    /// Set the smallest-magnitude `fraction` of all weights (biases are kept) to zero.
+    /// Returns the number of pruned weights.
+    pub fn prune_by_magnitude(&self, fraction: f64) -> usize {
+        let mut weights: Vec<Variable<'a>> = self
+            .layers
+            .iter()
+            .flat_map(|l| l.neurons.iter().flat_map(|n| n.w.iter().copied()))
+            .collect();
+        weights.sort_by(|a, b| a.data().abs().total_cmp(&b.data().abs()));
+
+        let n_prune = (weights.len() as f64 * fraction.clamp(0.0, 1.0)).round() as usize;
+        for w in &weights[..n_prune] {
+            w.set_data(0.0);
+        }
+        n_prune
+    }
+
+    /// Taylor importance |param * grad| of every parameter for a single output
+    pub fn parameter_saliency(&self, g: &'a Graph, input: &[f64], output_index: usize) -> Vec<f64> {
+        let params = self.parameters();
+        g.with_scratch_grads(&params, || {
+            let inputs = g.variables(input);
+            self.forward(&inputs)[output_index].backward();
+            params
+                .iter()
+                .map(|p| (p.data() * p.grad().unwrap_or_default()).abs())
+                .collect()
+        })
+    }
+
+    /// Export the weights as C arrays (row-major, one row per neuron) plus the layer dimensions as macros.
+    /// Non-finite weights are written as the `NAN` and `INFINITY` macros of `<math.h>`.
+    pub fn to_c_header(&self, name: &str) -> String {
+        let upper = name.to_uppercase();
+        let literal = |x: f64| match x {
+            x if x.is_nan() => "NAN".to_string(),
+            f64::INFINITY => "INFINITY".to_string(),
+            f64::NEG_INFINITY => "-INFINITY".to_string(),
+            x => format!("{x:?}"),
+        };
+        let join = |xs: Vec<f64>| xs.into_iter().map(literal).collect::<Vec<_>>().join(", ");
+
+        let mut header = "#include <math.h>\n\n".to_string();
+        header += &format!("#define {upper}_NUM_LAYERS {}\n", self.layers.len());
+        for (i, layer) in self.layers.iter().enumerate() {
+            let nin = layer.neurons.first().map_or(0, |n| n.w.len());
+            let weights = layer
+                .neurons
+                .iter()
+                .flat_map(|n| n.w.iter().map(|w| w.data()))
+                .collect();
+            let biases = layer.neurons.iter().map(|n| n.b.data()).collect();
+
+            header += &format!("\n#define {upper}_LAYER{i}_IN {nin}\n");
+            header += &format!("#define {upper}_LAYER{i}_OUT {}\n", layer.neurons.len());
+            header += &format!(
+                "static const double {name}_layer{i}_w[] = {{{}}};\n",
+                join(weights)
+            );
+            header += &format!(
+                "static const double {name}_layer{i}_b[] = {{{}}};\n",
+                join(biases)
+            );
+        }
+        header
+    }
+
+    /// Compare the backprop gradients of all parameters against central finite differences
+    /// of the softmax cross entropy loss and return the max relative error
+    pub fn gradcheck(&self, g: &'a Graph, input: &[f64], target: usize, eps: f64) -> f64 {
+        let checkpoint = g.len();
+        let loss = || {
+            let inputs = g.variables(input);
+            g.cross_entropy(&self.forward(&inputs), target)
+        };
+
+        let params = self.parameters();
+        let analytic: Vec<f64> = g.with_scratch_grads(&params, || {
+            loss().backward();
+            params
+                .iter()
+                .map(|p| p.grad().unwrap_or_default())
+                .collect()
+        });
+
+        let mut max_err: f64 = 0.0;
+        for (p, a) in params.iter().zip(analytic) {
+            let orig = p.data();
+            p.set_data(orig + eps);
+            let plus = loss().data();
+            g.truncate(checkpoint);
+            p.set_data(orig - eps);
+            let minus = loss().data();
+            g.truncate(checkpoint);
+            p.set_data(orig);
+
+            let numeric = (plus - minus) / (2.0 * eps);
+            let err = (a - numeric).abs() / (a.abs() + numeric.abs()).max(1e-6);
+            max_err = max_err.max(err);
+        }
+        max_err
+    }
+}
+
+/// Plain `f64` copy of a trained MLP for inference without arena or autograd
+#[derive(Debug, Clone)]
+pub struct InferenceMLP {
+    layers: Vec<InferenceLayer>,
+}
+
+#[derive(Debug, Clone)]
+struct InferenceLayer {
+    weights: Vec<Vec<f64>>, // one row of nin weights per neuron
+    biases: Vec<f64>,
+    activation: Activation,
+}
+
+impl InferenceMLP {
+    /// Extract the current parameter values of a trained MLP
+    pub fn from_trained(mlp: &MLP<'_>) -> Self {
+        let layers = mlp
+            .layers
+            .iter()
+            .map(|l| InferenceLayer {
+                weights: l
+                    .neurons
+                    .iter()
+                    .map(|n| n.w.iter().map(|w| w.data()).collect())
+                    .collect(),
+                biases: l.neurons.iter().map(|n| n.b.data()).collect(),
+                activation: l.neurons.first().map_or(Activation::None, |n| n.activation),
+            })
+            .collect();
+        Self { layers }
+    }
+
+    pub fn predict(&self, input: &[f64]) -> Vec<f64> {
+        // each layer reads one buffer and writes the other, the first one reads the input directly
+        let width = self
+            .layers
+            .iter()
+            .map(|l| l.biases.len())
+            .max()
+            .unwrap_or(0);
+        let mut out = Vec::with_capacity(width);
+        let mut next = Vec::with_capacity(width);
+        let mut x = input;
+        for layer in self.layers.iter() {
+            next.clear();
+            next.extend(layer.weights.iter().zip(&layer.biases).map(|(w, b)| {
+                let s = w.iter().zip(x).fold(*b, |acc, (w, x)| acc + w * x);
+                layer.activation.apply_value(s)
+            }));
+            std::mem::swap(&mut out, &mut next);
+            x = &out;
+        }
+        if self.layers.is_empty() {
+            out.extend_from_slice(input);
+        }
+        out
+    }
+}
+
+/// Evaluate the first output of a 2D-input model on a `resolution` x `resolution` grid spanning
+/// `x_range` and `y_range` (both ends included). `grid[row][col]` holds the value at (x[col], y[row]).
+/// Runs without gradient tracking, the graph is truncated back to its current length after every
+/// evaluation.
+pub fn decision_grid<'a>(
+    g: &'a Graph,
+    model: &MLP<'a>,
+    x_range: (f64, f64),
+    y_range: (f64, f64),
+    resolution: usize,
+) -> Vec<Vec<f64>> {
+    let checkpoint = g.len();
+    let lerp = |(lo, hi): (f64, f64), i: usize| {
+        if resolution > 1 {
+            lo + (hi - lo) * i as f64 / (resolution - 1) as f64
+        } else {
+            lo
+        }
+    };
+
+    (0..resolution)
+        .map(|row| {
+            (0..resolution)
+                .map(|col| {
+                    let inputs = [
+                        g.variable(lerp(x_range, col)),
+                        g.variable(lerp(y_range, row)),
+                    ];
+                    let out = g.no_grad(|| model.forward(&inputs)[0].data());
+                    g.truncate(checkpoint);
+                    out
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Fast gradient sign method: move every input by `epsilon` in the direction that increases the
+/// softmax cross entropy loss of `target`
+pub fn fgsm_attack<'a>(
+    g: &'a Graph,
+    model: &MLP<'a>,
+    input: &[f64],
+    target: usize,
+    epsilon: f64,
+) -> Vec<f64> {
+    g.with_scratch_grads(&model.parameters(), || {
+        let inputs = g.variables(input);
+        g.cross_entropy(&model.forward(&inputs), target).backward();
+
+        inputs
+            .iter()
+            .map(|x| {
+                let grad = x.grad().unwrap_or_default();
+                let sign = if grad > 0.0 {
+                    1.0
+                } else if grad < 0.0 {
+                    -1.0
+                } else {
+                    0.0
+                };
+                x.data() + epsilon * sign
+            })
+            .collect()
+    })
+}
+
+/// Signed contribution input[i] * d logit / d input[i] of every input feature to a class logit
+pub fn feature_contributions<'a>(
+    g: &'a Graph,
+    model: &MLP<'a>,
+    input: &[f64],
+    class: usize,
+) -> Vec<f64> {
+    g.with_scratch_grads(&model.parameters(), || {
+        let inputs = g.variables(input);
+        model.forward(&inputs)[class].backward();
+        inputs
+            .iter()
+            .map(|x| x.data() * x.grad().unwrap_or_default())
+            .collect()
+    })
 }
@@ -1,8 +1,500 @@
-use crate::graph::Variable;
+use crate::graph::{Graph, Variable};
+
+/// Common interface of all optimizers, allows generic training loops over `&mut dyn Optimizer`
+pub trait Optimizer {
+    /// Update all parameters from their current gradients
+    fn step(&mut self);
+
+    /// Remove the gradients of all parameters
+    fn zero_grad(&self);
+
+    /// The current learning rate
+    fn lr(&self) -> f64;
+
+    /// Change the learning rate, e.g. from a scheduler
+    fn set_lr(&mut self, lr: f64);
+}
 
 pub fn stochastic_gradiant_descent(parameters: &[Variable<'_>], lr: f64) {
-    for p in parameters.iter() {
-        let grad = p.grad().unwrap_or_default();
+    sgd_with_decay(parameters, lr, 0.0);
+}
+
+/// SGD with L2 regularization: `weight_decay * p` is added to the gradient, no graph nodes are built
+pub fn sgd_with_decay(parameters: &[Variable<'_>], lr: f64, weight_decay: f64) {
+    for p in parameters.iter().filter(|p| p.requires_grad()) {
+        let grad = p.grad().unwrap_or_default() + weight_decay * p.data();
         p.set_data(p.data() - lr * grad);
     }
 }
+
+/// Clamp every parameter's gradient into [-clip, clip], parameters without gradient are left alone
+pub fn clip_grad_value(parameters: &[Variable<'_>], clip: f64) {
+    assert!(clip >= 0.0, "clip_grad_value: clip must be non-negative");
+    for p in parameters.iter() {
+        if let Some(grad) = p.grad() {
+            p.set_grad(grad.clamp(-clip, clip));
+        }
+    }
+}
+
+/// Parameters with `requires_grad`, paired with their index for per-parameter optimizer state
+fn trainable<'p, 'a>(
+    parameters: &'p [Variable<'a>],
+) -> impl Iterator<Item = (usize, &'p Variable<'a>)> {
+    parameters
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.requires_grad())
+}
+
+fn zero_grads(parameters: &[Variable<'_>]) {
+    for p in parameters.iter() {
+        p.zero_grad();
+    }
+}
+
+/// Plain stochastic gradient descent as an `Optimizer`
+#[derive(Clone)]
+pub struct SGD<'a> {
+    params: Vec<Variable<'a>>,
+    lr: f64,
+}
+
+impl<'a> SGD<'a> {
+    pub fn new(params: &[Variable<'a>], lr: f64) -> Self {
+        Self {
+            params: params.to_vec(),
+            lr,
+        }
+    }
+}
+
+impl Optimizer for SGD<'_> {
+    fn step(&mut self) {
+        stochastic_gradiant_descent(&self.params, self.lr);
+    }
+
+    fn zero_grad(&self) {
+        zero_grads(&self.params);
+    }
+
+    fn lr(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+}
+
+/// First and second moment estimates per parameter, shared by Adam and Nadam
+#[derive(Clone)]
+struct Moments {
+    beta1: f64,
+    beta2: f64,
+    m: Vec<f64>,
+    v: Vec<f64>,
+    t: i32,
+}
+
+impl Moments {
+    fn new(n: usize, beta1: f64, beta2: f64) -> Self {
+        Self {
+            beta1,
+            beta2,
+            m: vec![0.0; n],
+            v: vec![0.0; n],
+            t: 0,
+        }
+    }
+
+    /// Advance the timestep, called once per optimizer step
+    fn tick(&mut self) {
+        self.t += 1;
+    }
+
+    /// Update the moments of parameter `i` and return the bias corrected (m_hat, v_hat)
+    fn update(&mut self, i: usize, grad: f64) -> (f64, f64) {
+        self.m[i] = self.beta1 * self.m[i] + (1.0 - self.beta1) * grad;
+        self.v[i] = self.beta2 * self.v[i] + (1.0 - self.beta2) * grad * grad;
+        (
+            self.m[i] / (1.0 - self.beta1.powi(self.t)),
+            self.v[i] / (1.0 - self.beta2.powi(self.t)),
+        )
+    }
+}
+
+/// Adam optimizer keeping bias corrected first and second moment estimates per parameter
+#[derive(Clone)]
+pub struct Adam<'a> {
+    params: Vec<Variable<'a>>,
+    lr: f64,
+    eps: f64,
+    moments: Moments,
+}
+
+impl<'a> Adam<'a> {
+    pub fn new(params: &[Variable<'a>], lr: f64, beta1: f64, beta2: f64, eps: f64) -> Self {
+        Self {
+            params: params.to_vec(),
+            lr,
+            eps,
+            moments: Moments::new(params.len(), beta1, beta2),
+        }
+    }
+}
+
+impl Optimizer for Adam<'_> {
+    fn step(&mut self) {
+        self.moments.tick();
+        for (i, p) in trainable(&self.params) {
+            let (m_hat, v_hat) = self.moments.update(i, p.grad().unwrap_or_default());
+            p.set_data(p.data() - self.lr * m_hat / (v_hat.sqrt() + self.eps));
+        }
+    }
+
+    fn zero_grad(&self) {
+        zero_grads(&self.params);
+    }
+
+    fn lr(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+}
+
+/// Adam with decoupled weight decay: the decay is applied directly to the parameter value
+/// instead of through the gradient, so it doesn't pollute the moment estimates
+#[derive(Clone)]
+pub struct AdamW<'a> {
+    adam: Adam<'a>,
+    weight_decay: f64,
+}
+
+impl<'a> AdamW<'a> {
+    pub fn new(
+        params: &[Variable<'a>],
+        lr: f64,
+        beta1: f64,
+        beta2: f64,
+        eps: f64,
+        weight_decay: f64,
+    ) -> Self {
+        Self {
+            adam: Adam::new(params, lr, beta1, beta2, eps),
+            weight_decay,
+        }
+    }
+}
+
+impl Optimizer for AdamW<'_> {
+    fn step(&mut self) {
+        for p in self.adam.params.iter().filter(|p| p.requires_grad()) {
+            p.set_data(p.data() - self.adam.lr * self.weight_decay * p.data());
+        }
+        self.adam.step();
+    }
+
+    fn zero_grad(&self) {
+        zero_grads(&self.adam.params);
+    }
+
+    fn lr(&self) -> f64 {
+        self.adam.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.adam.lr = lr;
+    }
+}
+
+/// Nadam optimizer: Adam with a Nesterov look-ahead on the bias corrected first moment.
+/// Compared to Adam the current gradient enters the momentum term one step earlier,
+/// which often converges faster at the same learning rate.
+#[derive(Clone)]
+pub struct Nadam<'a> {
+    params: Vec<Variable<'a>>,
+    lr: f64,
+    eps: f64,
+    moments: Moments,
+}
+
+impl<'a> Nadam<'a> {
+    pub fn new(params: &[Variable<'a>], lr: f64, beta1: f64, beta2: f64, eps: f64) -> Self {
+        Self {
+            params: params.to_vec(),
+            lr,
+            eps,
+            moments: Moments::new(params.len(), beta1, beta2),
+        }
+    }
+}
+
+impl Optimizer for Nadam<'_> {
+    fn step(&mut self) {
+        self.moments.tick();
+        let beta1 = self.moments.beta1;
+        let bias1 = 1.0 - beta1.powi(self.moments.t);
+        for (i, p) in trainable(&self.params) {
+            let grad = p.grad().unwrap_or_default();
+            let (m_hat, v_hat) = self.moments.update(i, grad);
+            let m_bar = beta1 * m_hat + (1.0 - beta1) * grad / bias1;
+            p.set_data(p.data() - self.lr * m_bar / (v_hat.sqrt() + self.eps));
+        }
+    }
+
+    fn zero_grad(&self) {
+        zero_grads(&self.params);
+    }
+
+    fn lr(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+}
+
+/// RMSProp optimizer dividing the gradient by a running average of its magnitude
+#[derive(Clone)]
+pub struct RMSProp<'a> {
+    params: Vec<Variable<'a>>,
+    lr: f64,
+    alpha: f64,
+    eps: f64,
+    avg_sq: Vec<f64>,
+}
+
+impl<'a> RMSProp<'a> {
+    pub fn new(params: &[Variable<'a>], lr: f64, alpha: f64, eps: f64) -> Self {
+        Self {
+            params: params.to_vec(),
+            lr,
+            alpha,
+            eps,
+            avg_sq: vec![0.0; params.len()],
+        }
+    }
+}
+
+impl Optimizer for RMSProp<'_> {
+    fn step(&mut self) {
+        for (i, p) in trainable(&self.params) {
+            let grad = p.grad().unwrap_or_default();
+            self.avg_sq[i] = self.alpha * self.avg_sq[i] + (1.0 - self.alpha) * grad * grad;
+            p.set_data(p.data() - self.lr * grad / (self.avg_sq[i].sqrt() + self.eps));
+        }
+    }
+
+    fn zero_grad(&self) {
+        zero_grads(&self.params);
+    }
+
+    fn lr(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+}
+
+/// SGD with (optionally Nesterov) momentum keeping a velocity per parameter
+#[derive(Clone)]
+pub struct MomentumSGD<'a> {
+    params: Vec<Variable<'a>>,
+    lr: f64,
+    mu: f64,
+    nesterov: bool,
+    velocity: Vec<f64>,
+}
+
+impl<'a> MomentumSGD<'a> {
+    pub fn new(params: &[Variable<'a>], lr: f64, mu: f64, nesterov: bool) -> Self {
+        Self {
+            params: params.to_vec(),
+            lr,
+            mu,
+            nesterov,
+            velocity: vec![0.0; params.len()],
+        }
+    }
+}
+
+impl Optimizer for MomentumSGD<'_> {
+    fn step(&mut self) {
+        for (i, p) in trainable(&self.params) {
+            let grad = p.grad().unwrap_or_default();
+            self.velocity[i] = self.mu * self.velocity[i] - self.lr * grad;
+            let update = if self.nesterov {
+                // look ahead along the new velocity
+                self.mu * self.velocity[i] - self.lr * grad
+            } else {
+                self.velocity[i]
+            };
+            p.set_data(p.data() + update);
+        }
+    }
+
+    fn zero_grad(&self) {
+        zero_grads(&self.params);
+    }
+
+    fn lr(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+}
+
+/// Adagrad optimizer scaling the learning rate by the accumulated squared gradients per parameter
+#[derive(Clone)]
+pub struct Adagrad<'a> {
+    params: Vec<Variable<'a>>,
+    lr: f64,
+    eps: f64,
+    sum_sq: Vec<f64>,
+}
+
+impl<'a> Adagrad<'a> {
+    pub fn new(params: &[Variable<'a>], lr: f64, eps: f64) -> Self {
+        Self {
+            params: params.to_vec(),
+            lr,
+            eps,
+            sum_sq: vec![0.0; params.len()],
+        }
+    }
+}
+
+impl Optimizer for Adagrad<'_> {
+    fn step(&mut self) {
+        for (i, p) in trainable(&self.params) {
+            let grad = p.grad().unwrap_or_default();
+            self.sum_sq[i] += grad * grad;
+            p.set_data(p.data() - self.lr * grad / (self.sum_sq[i].sqrt() + self.eps));
+        }
+    }
+
+    fn zero_grad(&self) {
+        zero_grads(&self.params);
+    }
+
+    fn lr(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+}
+
+/// Single Newton step `param -= grad / hessian` on a scalar parameter of `loss`.
+/// The exact second derivative is obtained by backpropagating through the gradient
+/// (see `Variable::backward_create_graph`), afterwards the gradient variables are removed
+/// and the graph below `loss` is recomputed. Zeros all gradients of the graph.
+/// Returns false and leaves `param` unchanged if `loss` doesn't depend on it or the second
+/// derivative is zero (e.g. a linear loss) or not finite.
+pub fn newton_step_1d<'a>(g: &'a Graph, param: Variable<'a>, loss: Variable<'a>) -> bool {
+    let checkpoint = g.len();
+    g.zero_grad();
+    loss.backward_create_graph();
+    let Some(grad) = param.grad_var() else {
+        // loss doesn't depend on param
+        g.truncate(checkpoint);
+        g.zero_grad();
+        return false;
+    };
+    g.zero_grad();
+    grad.backward();
+    let hessian = param.grad().unwrap_or_default();
+
+    let valid = hessian != 0.0 && hessian.is_finite();
+    if valid {
+        param.set_data(param.data() - grad.data() / hessian);
+    }
+    g.truncate(checkpoint);
+    g.forward(loss);
+    g.zero_grad();
+    valid
+}
+
+/// Multiplies the learning rate by `gamma` every `step_size` calls of `get_lr`
+#[derive(Debug, Clone)]
+pub struct StepLR {
+    step_size: usize,
+    gamma: f64,
+    current: usize,
+}
+
+impl StepLR {
+    pub fn new(step_size: usize, gamma: f64) -> Self {
+        assert!(step_size > 0, "StepLR step_size must be positive");
+        Self {
+            step_size,
+            gamma,
+            current: 0,
+        }
+    }
+
+    /// Scaled learning rate for the current step, advances the step counter
+    pub fn get_lr(&mut self, base_lr: f64) -> f64 {
+        let lr = base_lr * self.gamma.powi((self.current / self.step_size) as i32);
+        self.current += 1;
+        lr
+    }
+}
+
+/// Anneals the learning rate from `base_lr` down to `eta_min` along a half cosine over `t_max` calls of `get_lr`
+#[derive(Debug, Clone)]
+pub struct CosineAnnealingLR {
+    t_max: usize,
+    eta_min: f64,
+    current: usize,
+}
+
+impl CosineAnnealingLR {
+    pub fn new(t_max: usize, eta_min: f64) -> Self {
+        assert!(t_max > 0, "CosineAnnealingLR t_max must be positive");
+        Self {
+            t_max,
+            eta_min,
+            current: 0,
+        }
+    }
+
+    /// Scaled learning rate for the current step, advances the step counter.
+    /// Stays at `eta_min` after `t_max` steps.
+    pub fn get_lr(&mut self, base_lr: f64) -> f64 {
+        let t = self.current.min(self.t_max) as f64;
+        let cos = (std::f64::consts::PI * t / self.t_max as f64).cos();
+        self.current += 1;
+        self.eta_min + 0.5 * (base_lr - self.eta_min) * (1.0 + cos)
+    }
+}
+
+/// Decays the learning rate by `gamma` on every call of `get_lr`
+#[derive(Debug, Clone)]
+pub struct ExponentialLR {
+    gamma: f64,
+    current: i32,
+}
+
+impl ExponentialLR {
+    pub fn new(gamma: f64) -> Self {
+        Self { gamma, current: 0 }
+    }
+
+    /// Scaled learning rate for the current step, advances the step counter
+    pub fn get_lr(&mut self, base_lr: f64) -> f64 {
+        let lr = base_lr * self.gamma.powi(self.current);
+        self.current += 1;
+        lr
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+use std::ops::Index;
+
+use crate::graph::{Graph, Variable};
+
+/// Row-major matrix of variables with a `(rows, cols)` shape
+#[derive(Clone)]
+pub struct Tensor<'a> {
+    shape: (usize, usize),
+    data: Vec<Variable<'a>>,
+    graph: Option<&'a Graph>, // None for an empty tensor built by from_variables
+}
+
+impl<'a> Tensor<'a> {
+    /// New leaf variables from row-major `data`
+    pub fn new(g: &'a Graph, data: &[f64], rows: usize, cols: usize) -> Self {
+        let mut tensor = Self::from_variables(g.variables(data), rows, cols);
+        tensor.graph = Some(g);
+        tensor
+    }
+
+    /// Wrap existing row-major variables, e.g. the outputs of a layer
+    pub fn from_variables(data: Vec<Variable<'a>>, rows: usize, cols: usize) -> Self {
+        assert_eq!(
+            data.len(),
+            rows * cols,
+            "{} elements don't fit a {rows}x{cols} tensor",
+            data.len()
+        );
+        Self {
+            shape: (rows, cols),
+            graph: data.first().map(|v| v.graph),
+            data,
+        }
+    }
+
+    pub fn shape(&self) -> (usize, usize) {
+        self.shape
+    }
+
+    pub fn rows(&self) -> usize {
+        self.shape.0
+    }
+
+    pub fn cols(&self) -> usize {
+        self.shape.1
+    }
+
+    /// Element at `(row, col)`, None if out of bounds
+    pub fn get(&self, row: usize, col: usize) -> Option<Variable<'a>> {
+        (row < self.rows() && col < self.cols()).then(|| self.data[row * self.cols() + col])
+    }
+
+    /// The elements of `row`
+    pub fn row(&self, row: usize) -> &[Variable<'a>] {
+        assert!(
+            row < self.rows(),
+            "row {row} out of bounds for {:?}",
+            self.shape
+        );
+        &self.data[row * self.cols()..(row + 1) * self.cols()]
+    }
+
+    /// All elements in row-major order
+    pub fn as_slice(&self) -> &[Variable<'a>] {
+        &self.data
+    }
+
+    pub fn into_vec(self) -> Vec<Variable<'a>> {
+        self.data
+    }
+
+    /// Matrix product `(m, k) x (k, n) -> (m, n)`, every element is a `Graph::dot` of a row and a
+    /// column, so gradients flow into both operands. With `k == 0` every element is a zero
+    /// constant, which needs one operand created by `Tensor::new` to know the graph.
+    pub fn matmul(&self, other: &Tensor<'a>) -> Tensor<'a> {
+        let (m, k) = self.shape;
+        let (k2, n) = other.shape;
+        assert_eq!(
+            k, k2,
+            "matmul: inner dimensions of {:?} and {:?} differ",
+            self.shape, other.shape
+        );
+        let graph = self.graph.or(other.graph);
+        if m == 0 || n == 0 {
+            let mut empty = Tensor::from_variables(Vec::new(), m, n);
+            empty.graph = graph;
+            return empty;
+        }
+        let g = graph
+            .expect("matmul: the graph of empty operands is unknown, create them with Tensor::new");
+
+        let columns: Vec<Vec<_>> = (0..n)
+            .map(|j| (0..k).map(|i| other.data[i * n + j]).collect())
+            .collect();
+        let data = (0..m)
+            .flat_map(|i| columns.iter().map(move |col| (i, col)))
+            .map(|(i, col)| g.dot(self.row(i), col))
+            .collect();
+        Tensor::from_variables(data, m, n)
+    }
+}
+
+impl fmt::Debug for Tensor<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tensor")
+            .field("shape", &self.shape)
+            .field("data", &self.data)
+            .finish()
+    }
+}
+
+/// Shape-checked element access, panics if `(row, col)` is out of bounds
+impl<'a> Index<(usize, usize)> for Tensor<'a> {
+    type Output = Variable<'a>;
+
+    fn index(&self, (row, col): (usize, usize)) -> &Variable<'a> {
+        assert!(
+            row < self.rows() && col < self.cols(),
+            "index ({row}, {col}) out of bounds for {:?}",
+            self.shape
+        );
+        &self.data[row * self.cols() + col]
+    }
+}
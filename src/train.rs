@@ -0,0 +1,348 @@
+use std::{fs, io, ops::ControlFlow, path::Path};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{DataLoader, Dataset},
+    graph::{Graph, Variable},
+    nn::MLP,
+    optim::Optimizer,
+};
+
+/// How per-sample losses of a batch are combined before backprop.
+/// `Sum` scales the gradients (and thus the effective learning rate) with the batch size, `Mean` doesn't.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BatchReduction {
+    Sum,
+    #[default]
+    Mean,
+}
+
+impl BatchReduction {
+    /// Combine the per-sample losses into a single batch loss
+    pub fn reduce<'a>(&self, g: &'a Graph, losses: &[Variable<'a>]) -> Variable<'a> {
+        match self {
+            BatchReduction::Sum => g.sum(losses),
+            BatchReduction::Mean => g.mean(losses),
+        }
+    }
+}
+
+/// Sum of the per-sample gradients of `loss` over a batch, computed in parallel with rayon.
+/// `Graph` isn't thread safe, so every worker copies the parameter values into leaves of its own
+/// graph, passes them to `loss` (which rebuilds the model around them, e.g. with
+/// `MLP::from_parameters`) and backpropagates its share of the samples. The reduced gradients are
+/// returned in the order of `params`, e.g. to be stored with `set_grad` before an optimizer step.
+pub fn parallel_batch_gradients<S: Sync>(
+    params: &[Variable<'_>],
+    samples: &[S],
+    loss: impl for<'a> Fn(&'a Graph, &[Variable<'a>], &S) -> Variable<'a> + Sync,
+) -> Vec<f64> {
+    let values: Vec<f64> = params.iter().map(|p| p.data()).collect();
+    let chunk = samples.len().div_ceil(rayon::current_num_threads()).max(1);
+    samples
+        .par_chunks(chunk)
+        .map(|chunk| {
+            let g = Graph::new();
+            let params = g.variables(&values);
+            let checkpoint = g.len();
+            // the per-sample losses only share the parameters, so their gradients accumulate
+            for sample in chunk {
+                loss(&g, &params, sample).backward_and_free(checkpoint);
+            }
+            params
+                .iter()
+                .map(|p| p.grad().unwrap_or_default())
+                .collect::<Vec<_>>()
+        })
+        .reduce(
+            || vec![0.0; values.len()],
+            |a, b| a.iter().zip(b).map(|(a, b)| a + b).collect(),
+        )
+}
+
+/// Hooks into `Trainer::fit_with_callbacks`, e.g. for logging, validation or early stopping
+pub trait Callback {
+    /// Called after every optimizer step with the batch loss
+    fn on_batch_end(&mut self, _epoch: usize, _batch: usize, _loss: f64) {}
+
+    /// Called after every epoch with its mean batch loss, `Break` ends training
+    fn on_epoch_end(&mut self, _epoch: usize, _loss: f64) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
+
+/// Runs the usual training loop: for every batch of the loader build the inputs, forward the
+/// model, reduce the per-sample losses, backprop, step the optimizer and free the batch nodes again.
+/// The graph is truncated back to its length at construction, so create the model before.
+pub struct Trainer<'m, 'a, 'd, O, D, L>
+where
+    O: Optimizer,
+    D: Dataset + ?Sized,
+    L: FnMut(&[Variable<'a>], usize) -> Variable<'a>,
+{
+    graph: &'a Graph,
+    model: &'m MLP<'a>,
+    optimizer: O,
+    loss: L,
+    loader: DataLoader<'d, D>,
+    reduction: BatchReduction,
+    checkpoint: usize,
+}
+
+impl<'m, 'a, 'd, O, D, L> Trainer<'m, 'a, 'd, O, D, L>
+where
+    O: Optimizer,
+    D: Dataset + ?Sized,
+    L: FnMut(&[Variable<'a>], usize) -> Variable<'a>,
+{
+    /// `loss` maps the model output of a sample and its label to the sample loss
+    pub fn new(
+        graph: &'a Graph,
+        model: &'m MLP<'a>,
+        optimizer: O,
+        loss: L,
+        loader: DataLoader<'d, D>,
+    ) -> Self {
+        Self {
+            graph,
+            model,
+            optimizer,
+            loss,
+            loader,
+            reduction: BatchReduction::default(),
+            checkpoint: graph.len(),
+        }
+    }
+
+    pub fn with_reduction(mut self, reduction: BatchReduction) -> Self {
+        self.reduction = reduction;
+        self
+    }
+
+    /// Train for `epochs` epochs, returns the mean batch loss of every epoch
+    pub fn fit(&mut self, epochs: usize) -> Vec<f64> {
+        self.fit_with_callbacks(epochs, &mut [])
+    }
+
+    /// Like `fit`, calling the callbacks after every batch and epoch. Stops early if any callback
+    /// breaks, so fewer than `epochs` losses may be returned.
+    pub fn fit_with_callbacks(
+        &mut self,
+        epochs: usize,
+        callbacks: &mut [&mut dyn Callback],
+    ) -> Vec<f64> {
+        let g = self.graph;
+        let mut epoch_losses = Vec::with_capacity(epochs);
+        for epoch in 0..epochs {
+            let mut total = 0.0;
+            let mut num_batches = 0;
+            for (batch, (features, labels)) in self.loader.epoch().enumerate() {
+                let inputs: Vec<_> = features.iter().map(|f| g.variables(f)).collect();
+                let outputs = self.model.forward_batch(&inputs);
+                let losses: Vec<_> = outputs
+                    .iter()
+                    .zip(labels)
+                    .map(|(output, label)| (self.loss)(output, label))
+                    .collect();
+                let loss = self.reduction.reduce(g, &losses);
+                let value = loss.data();
+                total += value;
+                num_batches += 1;
+
+                loss.backward_and_free(self.checkpoint);
+                self.optimizer.step();
+                self.optimizer.zero_grad();
+                for callback in callbacks.iter_mut() {
+                    callback.on_batch_end(epoch, batch, value);
+                }
+            }
+            let epoch_loss = total / num_batches.max(1) as f64;
+            epoch_losses.push(epoch_loss);
+
+            let mut stop = false;
+            for callback in callbacks.iter_mut() {
+                stop |= callback.on_epoch_end(epoch, epoch_loss).is_break();
+            }
+            if stop {
+                break;
+            }
+        }
+        epoch_losses
+    }
+
+    pub fn optimizer(&self) -> &O {
+        &self.optimizer
+    }
+}
+
+/// Hyperparameters and architecture of a training run, stored as JSON for reproducibility
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrainConfig {
+    pub lr: f64,
+    pub batch_size: usize,
+    pub epochs: usize,
+    pub optimizer: String,
+    pub seed: u64,
+    /// MLP input size, see `Graph::mlp`
+    pub nin: i16,
+    /// MLP layer output sizes, see `Graph::mlp`
+    pub nouts: Vec<i16>,
+}
+
+impl TrainConfig {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("TrainConfig is always serializable")
+    }
+
+    pub fn from_json(s: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(s)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::from_json(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// Guards an optimizer against diverging training runs.
+/// After every good step the parameters and the optimizer state are checkpointed. A NaN/inf loss
+/// or gradient restores the last checkpoint and halves the learning rate instead of stepping.
+pub struct TrainGuard<'a, O: Optimizer + Clone> {
+    params: Vec<Variable<'a>>,
+    optimizer: O,
+    good_params: Vec<f64>,
+    good_optimizer: O,
+}
+
+impl<'a, O: Optimizer + Clone> TrainGuard<'a, O> {
+    pub fn new(params: &[Variable<'a>], optimizer: O) -> Self {
+        Self {
+            params: params.to_vec(),
+            good_params: params.iter().map(|p| p.data()).collect(),
+            good_optimizer: optimizer.clone(),
+            optimizer,
+        }
+    }
+
+    /// Backprop `loss`, step the optimizer and zero the parameter gradients.
+    /// Returns false if the step was rejected and training was rolled back.
+    pub fn step(&mut self, loss: Variable<'a>) -> bool {
+        let finite = loss.backward_checked()
+            && self
+                .params
+                .iter()
+                .all(|p| p.grad().unwrap_or_default().is_finite());
+        if !finite {
+            self.restore();
+            return false;
+        }
+
+        self.optimizer.step();
+        self.optimizer.zero_grad();
+        if self.params.iter().any(|p| !p.data().is_finite()) {
+            self.restore();
+            return false;
+        }
+
+        self.good_params = self.params.iter().map(|p| p.data()).collect();
+        self.good_optimizer = self.optimizer.clone();
+        true
+    }
+
+    pub fn optimizer(&self) -> &O {
+        &self.optimizer
+    }
+
+    /// Roll back to the last checkpoint with a halved learning rate
+    fn restore(&mut self) {
+        for (p, &data) in self.params.iter().zip(&self.good_params) {
+            p.set_data(data);
+        }
+        let lr = self.good_optimizer.lr() / 2.0;
+        self.good_optimizer.set_lr(lr);
+        self.optimizer = self.good_optimizer.clone();
+        self.optimizer.zero_grad();
+    }
+}
+
+/// Exponential moving average of the loss for smoother logging
+#[derive(Debug, Clone)]
+pub struct EmaLoss {
+    decay: f64,
+    value: Option<f64>,
+}
+
+impl EmaLoss {
+    /// `decay` close to 1.0 smooths more
+    pub fn new(decay: f64) -> Self {
+        Self { decay, value: None }
+    }
+
+    /// Feed a new loss and return the updated average, the first loss seeds the average
+    pub fn update(&mut self, loss: f64) -> f64 {
+        let ema = match self.value {
+            Some(ema) => self.decay * ema + (1.0 - self.decay) * loss,
+            None => loss,
+        };
+        self.value = Some(ema);
+        ema
+    }
+
+    pub fn value(&self) -> Option<f64> {
+        self.value
+    }
+}
+
+/// Stops training once the validation loss didn't improve by more than `min_delta` for `patience`
+/// epochs in a row
+#[derive(Debug, Clone)]
+pub struct EarlyStopping {
+    patience: usize,
+    min_delta: f64,
+    best: f64,
+    wait: usize,
+}
+
+impl EarlyStopping {
+    pub fn new(patience: usize, min_delta: f64) -> Self {
+        assert!(patience > 0, "EarlyStopping patience must be positive");
+        Self {
+            patience,
+            min_delta,
+            best: f64::INFINITY,
+            wait: 0,
+        }
+    }
+
+    /// Feed the loss of the latest epoch, returns true when training should stop
+    pub fn should_stop(&mut self, val_loss: f64) -> bool {
+        if val_loss < self.best - self.min_delta {
+            self.best = val_loss;
+            self.wait = 0;
+        } else {
+            self.wait += 1;
+        }
+        self.wait >= self.patience
+    }
+
+    /// Lowest loss seen so far
+    pub fn best(&self) -> f64 {
+        self.best
+    }
+}
+
+/// Stops on the epoch training loss, validate in another callback to stop on the validation loss
+impl Callback for EarlyStopping {
+    fn on_epoch_end(&mut self, _epoch: usize, loss: f64) -> ControlFlow<()> {
+        if self.should_stop(loss) {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use backprop_rs::graph::Graph;
+
+/// Counts every allocation of this test binary
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocations_during(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+#[test]
+fn test_allocations_on_chain() {
+    let n = 10_000;
+    let g = Graph::new();
+    let x = g.variable(1.0);
+    let mut s = x;
+    s += x; // warm up the reusable buffers
+    let forward = allocations_during(|| {
+        for _ in 1..n {
+            s += x;
+        }
+    });
+    // the children list of every node plus the arena growing, the children data isn't collected
+    assert!(forward < n + 100, "{forward} allocations for {n} nodes");
+
+    s.backward(); // warm up the reusable buffers
+    g.zero_grad();
+    let backward = allocations_during(|| s.backward());
+
+    assert_eq!(x.grad(), Some(n as f64 + 1.0));
+    // nothing per node, only the topological order and its stack grow
+    assert!(backward < 64, "{backward} allocations for {n} nodes");
+}
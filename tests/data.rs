@@ -0,0 +1,262 @@
+use std::path::PathBuf;
+
+use rand::SeedableRng;
+
+use backprop_rs::data::{Augmentation, CsvData, DataLoader, Dataset, MnistData, Normalization};
+
+/// Write a tiny MNIST-format dataset (idx files) with the given 2x2 images
+fn write_idx(name: &str, images: &[[u8; 4]], labels: &[u8]) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir();
+    let images_path = dir.join(format!("backprop_rs_{name}-images-idx3-ubyte"));
+    let labels_path = dir.join(format!("backprop_rs_{name}-labels-idx1-ubyte"));
+
+    let mut bytes = Vec::new();
+    for header in [2051, images.len() as u32, 2, 2] {
+        bytes.extend(header.to_be_bytes());
+    }
+    bytes.extend(images.iter().flatten());
+    std::fs::write(&images_path, bytes).unwrap();
+
+    let mut bytes = Vec::new();
+    for header in [2049, labels.len() as u32] {
+        bytes.extend(header.to_be_bytes());
+    }
+    bytes.extend(labels);
+    std::fs::write(&labels_path, bytes).unwrap();
+
+    (images_path, labels_path)
+}
+
+const IMAGES: [[u8; 4]; 3] = [[0, 255, 128, 0], [10, 20, 30, 40], [255, 255, 0, 0]];
+
+#[test]
+fn test_load_normalizations() {
+    let (images, labels) = write_idx("normalization", &IMAGES, &[3, 1, 4]);
+
+    let raw = MnistData::load_normalized(&images, &labels, Normalization::None).unwrap();
+    assert_eq!(raw.images[0], vec![0.0, 255.0, 128.0, 0.0]);
+    assert_eq!(raw.labels, vec![3, 1, 4]);
+
+    let unit = MnistData::load(&images, &labels).unwrap();
+    assert_eq!(unit.normalization(), Normalization::UnitRange);
+    assert!(
+        unit.images
+            .iter()
+            .flatten()
+            .all(|p| (0.0..=1.0).contains(p))
+    );
+    assert_eq!(unit.images[0][1], 1.0);
+
+    let standardized =
+        MnistData::load_normalized(&images, &labels, Normalization::Standardize).unwrap();
+    assert_eq!(standardized.normalization(), Normalization::Standardize);
+    // standardizing the MNIST statistics themselves gives mean 0 / std 1
+    let pixels: Vec<f64> = unit.images.iter().flatten().copied().collect();
+    let mean = pixels.iter().sum::<f64>() / pixels.len() as f64;
+    let std_mean = standardized.images.iter().flatten().sum::<f64>() / pixels.len() as f64;
+    assert!((std_mean - (mean - 0.1307) / 0.3081).abs() < 1e-12);
+}
+
+#[test]
+fn test_standardized_mnist_statistics_have_zero_mean() {
+    // pixels distributed like MNIST: mostly background, some full strokes
+    let n = Normalization::Standardize;
+    let mean_pixel = (Normalization::MNIST_MEAN * 255.0).round() as u8;
+    assert!(n.apply(mean_pixel).abs() < 0.01);
+    assert!(n.apply(0) < 0.0 && n.apply(255) > 0.0);
+}
+
+#[test]
+fn test_load_gzipped() {
+    use flate2::{Compression, write::GzEncoder};
+    use std::io::Write;
+
+    let (images, labels) = write_idx("plain", &IMAGES, &[3, 1, 4]);
+    let gzip = |path: &PathBuf| {
+        let gz_path = path.with_extension("gz");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&std::fs::read(path).unwrap()).unwrap();
+        std::fs::write(&gz_path, encoder.finish().unwrap()).unwrap();
+        gz_path
+    };
+
+    let plain = MnistData::load(&images, &labels).unwrap();
+    let gzipped = MnistData::load(&gzip(&images), &gzip(&labels)).unwrap();
+    assert_eq!(gzipped.images, plain.images);
+    assert_eq!(gzipped.labels, plain.labels);
+}
+
+#[test]
+fn test_load_rejects_wrong_magic() {
+    let (images, labels) = write_idx("magic", &IMAGES, &[3, 1, 4]);
+    // swapped files
+    let err = MnistData::load(&labels, &images).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+fn toy_mnist(n: usize) -> MnistData {
+    let images: Vec<[u8; 4]> = (0..n).map(|i| [i as u8, 0, 0, 0]).collect();
+    let labels: Vec<u8> = (0..n).map(|i| i as u8).collect();
+    let (images, labels) = write_idx(&format!("toy{n}"), &images, &labels);
+    MnistData::load_normalized(&images, &labels, Normalization::None).unwrap()
+}
+
+#[test]
+fn test_dataloader_shuffles_each_epoch() {
+    let data = toy_mnist(10);
+    let mut loader = DataLoader::new(&data, 3, 42);
+    assert_eq!(loader.num_batches(), 4);
+
+    let mut orders = Vec::new();
+    for _ in 0..2 {
+        let mut order = Vec::new();
+        for (images, labels) in loader.epoch() {
+            assert!(images.len() <= 3);
+            assert_eq!(images.len(), labels.len());
+            for (image, &label) in images.iter().zip(&labels) {
+                // images and labels stay paired
+                assert_eq!(image[0], label as f64);
+            }
+            order.extend(labels);
+        }
+        let mut seen = order.clone();
+        seen.sort();
+        assert_eq!(seen, (0..10).collect::<Vec<usize>>());
+        orders.push(order);
+    }
+    assert_ne!(orders[0], orders[1]);
+}
+
+#[test]
+fn test_one_hot() {
+    let encoded = MnistData::one_hot(3, 10);
+    assert_eq!(encoded.len(), 10);
+    for (i, &v) in encoded.iter().enumerate() {
+        assert_eq!(v, if i == 3 { 1.0 } else { 0.0 });
+    }
+}
+
+#[test]
+#[should_panic(expected = "out of range")]
+fn test_one_hot_rejects_label_out_of_range() {
+    MnistData::one_hot(10, 10);
+}
+
+#[test]
+fn test_split_partitions_samples() {
+    let data = toy_mnist(20);
+    let (train, val) = data.split(0.25, 7);
+    assert_eq!(train.len(), 15);
+    assert_eq!(val.len(), 5);
+    assert_eq!(val.normalization(), data.normalization());
+
+    let mut all: Vec<u8> = train.labels.iter().chain(&val.labels).copied().collect();
+    all.sort();
+    // every sample ends up in exactly one of the splits
+    assert_eq!(all, (0..20).collect::<Vec<u8>>());
+    for (image, &label) in val.images.iter().zip(&val.labels) {
+        assert_eq!(image[0], label as f64);
+    }
+}
+
+/// In-memory points on a line, labeled by their sign
+struct Line(Vec<[f64; 1]>);
+
+impl Dataset for Line {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn get(&self, i: usize) -> (&[f64], usize) {
+        (&self.0[i], (self.0[i][0] > 0.0) as usize)
+    }
+}
+
+#[test]
+fn test_dataloader_over_custom_dataset() {
+    let data = Line((-3..=3).map(|x| [x as f64]).collect());
+    let mut loader = DataLoader::new(&data, 2, 0);
+    assert_eq!(loader.num_batches(), 4);
+
+    let mut xs = Vec::new();
+    for (features, labels) in loader.epoch() {
+        for (x, label) in features.iter().zip(labels) {
+            assert_eq!(label, (x[0] > 0.0) as usize);
+            xs.push(x[0]);
+        }
+    }
+    xs.sort_by(f64::total_cmp);
+    assert_eq!(xs, vec![-3.0, -2.0, -1.0, 0.0, 1.0, 2.0, 3.0]);
+}
+
+#[test]
+fn test_csv_data_from_reader() {
+    let csv = "x,label,y\n0.5,1,-2\n1.5,0,3e2\n";
+    let data = CsvData::from_reader(csv.as_bytes(), 1).unwrap();
+    assert_eq!(data.len(), 2);
+    assert_eq!(data.get(0), (&[0.5, -2.0][..], 1));
+    assert_eq!(data.get(1), (&[1.5, 300.0][..], 0));
+
+    // without header
+    let data = CsvData::from_reader("1,2,0\n".as_bytes(), 2).unwrap();
+    assert_eq!(data.get(0), (&[1.0, 2.0][..], 0));
+}
+
+#[test]
+fn test_csv_data_rejects_malformed_rows() {
+    for csv in [
+        "x,label\n1,0\nfoo,1\n",
+        "1,0\n2,1,3\n",
+        "1,0\n2,-1\n",
+        "1,0\n2\n",
+        // a malformed first row isn't mistaken for a header
+        "foo,1\n2,0\n",
+        "1.5,x\n2,0\n",
+        "1.5,-1\n2,0\n",
+    ] {
+        let err = CsvData::from_reader(csv.as_bytes(), 1).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line "), "{err}");
+    }
+}
+
+#[test]
+fn test_augmentation_keeps_shape_and_labels() {
+    let image: Vec<f64> = (0..28 * 28).map(|i| (i % 7) as f64 / 7.0).collect();
+
+    let augmentation = Augmentation::mnist();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let augmented = augmentation.apply(&image, &mut rng);
+    assert_eq!(augmented.len(), image.len());
+    assert_ne!(augmented, image);
+
+    // a pure shift pads with zeros and keeps the pixel values
+    let shift_only = Augmentation {
+        noise_std: 0.0,
+        ..augmentation
+    };
+    let ones = vec![1.0; 28 * 28];
+    let shifted = shift_only.apply(&ones, &mut rng);
+    assert!(shifted.iter().all(|&p| p == 0.0 || p == 1.0));
+}
+
+#[test]
+fn test_dataloader_augments_each_epoch() {
+    let data = toy_mnist(4);
+    let augmentation = Augmentation {
+        width: 2,
+        height: 2,
+        max_shift: 1,
+        noise_std: 0.1,
+    };
+    let mut loader = DataLoader::new(&data, 4, 0).with_augmentation(augmentation);
+
+    let (images, labels) = loader.epoch().next().unwrap();
+    let mut sorted = labels.clone();
+    sorted.sort();
+    assert_eq!(sorted, vec![0, 1, 2, 3]);
+    for (image, &label) in images.iter().zip(&labels) {
+        assert_eq!(image.len(), 4);
+        assert_ne!(image.as_ref(), data.images[label].as_slice());
+    }
+}
@@ -0,0 +1,63 @@
+use backprop_rs::{
+    graph::Graph,
+    metrics::{
+        accuracy, accuracy_rate, argmax, confusion_matrix, format_confusion_matrix,
+        gradient_sparsity,
+    },
+};
+
+#[test]
+fn test_gradient_sparsity() {
+    let g = Graph::new();
+    let params: Vec<_> = (0..5).map(|_| g.variable(1.0)).collect();
+    // grads: 3.0, 1e-9, 0.0 (relu), None, None
+    let loss = g.variable(3.0) * params[0]
+        + g.variable(1e-9) * params[1]
+        + (g.variable(-1.0) * params[2]).relu();
+    loss.backward();
+
+    assert_eq!(gradient_sparsity(&params, 1e-6), 0.8);
+    assert_eq!(gradient_sparsity(&params, 0.0), 0.0);
+    assert_eq!(gradient_sparsity(&params, 10.0), 1.0);
+    assert_eq!(gradient_sparsity(&[], 1e-6), 0.0);
+}
+
+#[test]
+fn test_accuracy() {
+    let g = Graph::new();
+    let logits = g.variables(&[0.1, 2.0, -1.0, 1.9]);
+    assert!(accuracy(&logits, 1));
+    assert!(!accuracy(&logits, 3));
+
+    let with_nan = g.variables(&[0.1, f64::NAN, -1.0]);
+    assert!(accuracy(&with_nan, 0));
+    assert!(!accuracy(&[], 0));
+}
+
+#[test]
+fn test_accuracy_rate() {
+    assert_eq!(accuracy_rate(&[1, 2, 3, 4], &[1, 0, 3, 0]), 0.5);
+    assert_eq!(accuracy_rate(&[], &[]), 0.0);
+}
+
+#[test]
+fn test_confusion_matrix() {
+    let targets = [0, 0, 1, 1, 2, 2];
+    let predictions = [0, 1, 1, 1, 0, 2];
+    let matrix = confusion_matrix(&predictions, &targets, 3);
+    assert_eq!(matrix, vec![vec![1, 1, 0], vec![0, 2, 0], vec![1, 0, 1]]);
+
+    assert_eq!(
+        format_confusion_matrix(&matrix),
+        "  | 0 1 2\n---------\n0 | 1 1 0\n1 | 0 2 0\n2 | 1 0 1\n"
+    );
+}
+
+#[test]
+fn test_argmax() {
+    let g = Graph::new();
+    assert_eq!(argmax(&g.variables(&[0.1, 2.0, -1.0, 2.0])), 1);
+    assert_eq!(argmax(&g.variables(&[f64::NAN, -3.0, -1.0, f64::NAN])), 2);
+    assert_eq!(argmax(&g.variables(&[f64::NAN, f64::NAN])), 0);
+    assert_eq!(argmax(&g.variables(&[f64::NEG_INFINITY, f64::INFINITY])), 1);
+}
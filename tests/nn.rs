@@ -0,0 +1,670 @@
+use backprop_rs::{
+    graph::Graph,
+    nn::{
+        Activation, BatchNorm1d, Dropout, Embedding, InferenceMLP, Init, Layer, LayerNorm, Linear,
+        MLP, Neuron, decision_grid, feature_contributions, fgsm_attack,
+    },
+    optim::{Adam, Optimizer, stochastic_gradiant_descent},
+};
+
+#[test]
+fn test_mlp_gradcheck() {
+    let g = Graph::new();
+    let mlp = g.mlp(3, vec![4, 3]);
+    let err = mlp.gradcheck(&g, &[0.5, -1.0, 2.0], 1, 1e-5);
+    assert!(err < 1e-4, "max relative error {err}");
+}
+
+#[test]
+fn test_parameter_saliency() {
+    let g = Graph::new();
+    let mlp = g.mlp(3, vec![1]);
+    let params = mlp.parameters(); // [w0, w1, w2, b]
+    for (p, w) in params.iter().zip([0.1, -2.0, 0.5, 0.0]) {
+        p.set_data(w);
+    }
+
+    let saliency = mlp.parameter_saliency(&g, &[1.0, 1.0, 1.0], 0);
+    assert_eq!(saliency.len(), 4);
+    assert_eq!(saliency[..3], [0.1, 2.0, 0.5]);
+
+    let mut ranking: Vec<usize> = (0..3).collect();
+    ranking.sort_by(|&i, &j| saliency[j].total_cmp(&saliency[i]));
+    assert_eq!(ranking, vec![1, 2, 0]);
+}
+
+#[test]
+fn test_analysis_helpers_keep_accumulated_gradients() {
+    let g = Graph::new();
+    let mlp = MLP::seeded(&g, 3, vec![4, 2], 7);
+    let params = mlp.parameters();
+    let inputs = g.variables(&[0.5, -1.0, 2.0]);
+    g.sum(&mlp.forward(&inputs)).backward();
+    let accumulated: Vec<_> = params.iter().map(|p| p.grad()).collect();
+    let checkpoint = g.len();
+
+    let input = [1.0, 0.0, -1.0];
+    mlp.parameter_saliency(&g, &input, 0);
+    mlp.gradcheck(&g, &input, 1, 1e-5);
+    fgsm_attack(&g, &mlp, &input, 0, 0.1);
+    feature_contributions(&g, &mlp, &input, 1);
+
+    let after: Vec<_> = params.iter().map(|p| p.grad()).collect();
+    assert_eq!(after, accumulated);
+    assert_eq!(g.len(), checkpoint);
+}
+
+#[test]
+fn test_prune_by_magnitude() {
+    let g = Graph::new();
+    let mlp = g.mlp(4, vec![4, 2]);
+    let before: Vec<f64> = mlp.parameters().iter().map(|p| p.data()).collect();
+
+    let pruned = mlp.prune_by_magnitude(0.5);
+    let after: Vec<f64> = mlp.parameters().iter().map(|p| p.data()).collect();
+
+    // 4*4 + 4*2 = 24 weights, biases start at 0.0 anyway
+    assert_eq!(pruned, 12);
+    let nonzero_before: Vec<f64> = before.iter().copied().filter(|&w| w != 0.0).collect();
+    let kept: Vec<f64> = after.iter().copied().filter(|&w| w != 0.0).collect();
+    assert_eq!(nonzero_before.len() - kept.len(), 12);
+
+    // every surviving weight is unchanged and larger than every pruned one
+    let threshold = kept.iter().map(|w| w.abs()).fold(f64::INFINITY, f64::min);
+    for (b, a) in before.iter().zip(&after) {
+        if *a != 0.0 {
+            assert_eq!(a, b);
+        } else {
+            assert!(b.abs() <= threshold);
+        }
+    }
+}
+
+#[test]
+fn test_activation_stats() {
+    let g = Graph::new();
+    let mlp = g.mlp(2, vec![2, 1]);
+    // layer 0: n0 = relu(x0 + x1), n1 = relu(x0 - x1); layer 1: n0 + n1
+    let weights = [1.0, 1.0, 0.0, 1.0, -1.0, 0.0, 1.0, 1.0, 0.0];
+    for (p, w) in mlp.parameters().iter().zip(weights) {
+        p.set_data(w);
+    }
+    let checkpoint = g.len();
+
+    let batch = vec![vec![1.0, 1.0], vec![2.0, 0.0]];
+    let stats = mlp.activation_stats(&g, &batch);
+
+    // layer 0 outputs: [2, 0] and [2, 2] => mean 1.5, std sqrt(0.75)
+    assert_eq!(stats.len(), 2);
+    assert!((stats[0].0 - 1.5).abs() < 1e-12);
+    assert!((stats[0].1 - 0.75f64.sqrt()).abs() < 1e-12);
+    // layer 1 outputs: 2 and 4 => mean 3, std 1
+    assert!((stats[1].0 - 3.0).abs() < 1e-12);
+    assert!((stats[1].1 - 1.0).abs() < 1e-12);
+    assert_eq!(g.len(), checkpoint);
+}
+
+#[test]
+fn test_inference_mlp_matches_forward() {
+    let g = Graph::new();
+    let mlp = g.mlp(3, vec![8, 8, 2]);
+    let inference = InferenceMLP::from_trained(&mlp);
+
+    for _ in 0..10 {
+        let input: Vec<f64> = (0..3).map(|_| rand::random::<f64>() * 2. - 1.).collect();
+        let vars: Vec<_> = input.iter().map(|&x| g.variable(x)).collect();
+        let expected: Vec<f64> = mlp.forward(&vars).iter().map(|v| v.data()).collect();
+        let predicted = inference.predict(&input);
+
+        assert_eq!(predicted.len(), 2);
+        for (p, e) in predicted.iter().zip(&expected) {
+            assert!((p - e).abs() < 1e-12);
+        }
+    }
+}
+
+#[test]
+fn test_decision_grid_on_xor() {
+    let xs = [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
+    let ys = [0.0, 1.0, 1.0, 0.0];
+
+    let g = Graph::new();
+    let mlp = MLP::seeded(&g, 2, vec![16, 1], 1);
+    let params = mlp.parameters();
+    let checkpoint = g.len();
+    for _ in 0..500 {
+        let mut loss = g.variable(0.0);
+        for (x, &y) in xs.iter().zip(&ys) {
+            let pred = mlp.forward(&[g.variable(x[0]), g.variable(x[1])]);
+            loss += (pred[0] - g.variable(y)).pow(2.0);
+        }
+        loss.backward();
+        stochastic_gradiant_descent(&params, 0.05);
+        g.zero_grad();
+        g.truncate(checkpoint);
+    }
+
+    let grid = decision_grid(&g, &mlp, (0.0, 1.0), (0.0, 1.0), 5);
+    assert_eq!(grid.len(), 5);
+    assert!(grid.iter().all(|row| row.len() == 5));
+    assert_eq!(g.len(), checkpoint);
+
+    // corners are the XOR inputs: grid[row = y][col = x]
+    assert!(grid[0][0].abs() < 0.2, "{grid:?}");
+    assert!((grid[0][4] - 1.0).abs() < 0.2, "{grid:?}");
+    assert!((grid[4][0] - 1.0).abs() < 0.2, "{grid:?}");
+    assert!(grid[4][4].abs() < 0.2, "{grid:?}");
+}
+
+#[test]
+fn test_to_c_header() {
+    let g = Graph::new();
+    let mlp = g.mlp(3, vec![4, 2]);
+    let header = mlp.to_c_header("net");
+
+    assert!(header.contains("#define NET_NUM_LAYERS 2"));
+    assert!(header.contains("#define NET_LAYER0_IN 3"));
+    assert!(header.contains("#define NET_LAYER0_OUT 4"));
+    assert!(header.contains("#define NET_LAYER1_IN 4"));
+    assert!(header.contains("#define NET_LAYER1_OUT 2"));
+
+    let entries = |array: &str| {
+        let line = header.lines().find(|l| l.contains(array)).unwrap();
+        let body = &line[line.find('{').unwrap() + 1..line.find('}').unwrap()];
+        body.split(',').count()
+    };
+    assert_eq!(entries("net_layer0_w[]"), 12);
+    assert_eq!(entries("net_layer0_b[]"), 4);
+    assert_eq!(entries("net_layer1_w[]"), 8);
+    assert_eq!(entries("net_layer1_b[]"), 2);
+
+    // non-finite weights become valid C expressions
+    let params = mlp.parameters();
+    params[0].set_data(f64::NAN);
+    params[1].set_data(f64::NEG_INFINITY);
+    let header = mlp.to_c_header("net");
+    assert!(header.starts_with("#include <math.h>\n"));
+    assert!(header.contains("{NAN, -INFINITY, "));
+}
+
+#[test]
+fn test_mlp_with_tanh_hidden_layer() {
+    let g = Graph::new();
+    let mlp = MLP::with_activations(&g, 2, vec![2, 1], &[Activation::Tanh, Activation::None]);
+    // hidden: tanh(x0 + x1), tanh(x0 - x1); output: 2 h0 - h1 + 0.5
+    let weights = [1.0, 1.0, 0.0, 1.0, -1.0, 0.0, 2.0, -1.0, 0.5];
+    for (p, w) in mlp.parameters().iter().zip(weights) {
+        p.set_data(w);
+    }
+
+    let out = mlp.forward(&[g.variable(0.3), g.variable(0.1)]);
+    let expected = 2.0 * 0.4f64.tanh() - 0.2f64.tanh() + 0.5;
+    assert!((out[0].data() - expected).abs() < 1e-12);
+
+    // linear output: unbounded, unlike the tanh hidden units
+    let big = mlp.forward(&[g.variable(100.0), g.variable(0.0)]);
+    assert!((big[0].data() - 1.5).abs() < 1e-9);
+
+    let inference = InferenceMLP::from_trained(&mlp);
+    assert!((inference.predict(&[0.3, 0.1])[0] - expected).abs() < 1e-12);
+}
+
+#[test]
+fn test_linear_without_bias() {
+    let g = Graph::new();
+    let with_bias = Linear::new(&g, 3, 2, true);
+    let without_bias = Linear::new(&g, 3, 2, false);
+    assert_eq!(with_bias.parameters().len(), 3 * 2 + 2);
+    assert_eq!(without_bias.parameters().len(), 3 * 2);
+
+    let weights = [1.0, 2.0, 3.0, -1.0, 0.0, 1.0];
+    for (p, w) in without_bias.parameters().iter().zip(weights) {
+        p.set_data(w);
+    }
+    let out = without_bias.forward(&[g.variable(1.0), g.variable(1.0), g.variable(2.0)]);
+    assert_eq!(out[0].data(), 9.0);
+    assert_eq!(out[1].data(), 1.0);
+}
+
+#[test]
+fn test_dropout() {
+    let g = Graph::new();
+    let x: Vec<_> = (0..10_000).map(|_| g.variable(1.0)).collect();
+    let mut dropout = Dropout::new(0.3, 42);
+
+    let out = dropout.forward(&g, &x);
+    let dropped = out.iter().filter(|v| v.data() == 0.0).count();
+    assert!((dropped as f64 / x.len() as f64 - 0.3).abs() < 0.02);
+    assert!(
+        out.iter()
+            .all(|v| v.data() == 0.0 || (v.data() - 1.0 / 0.7).abs() < 1e-12)
+    );
+
+    // gradients only reach the kept units
+    let sum = out.iter().skip(1).fold(out[0], |acc, &v| acc + v);
+    sum.backward();
+    for (xi, oi) in x.iter().zip(&out) {
+        assert_eq!(xi.grad().unwrap(), oi.data());
+    }
+
+    // same seed, same mask
+    let again: Vec<f64> = Dropout::new(0.3, 42)
+        .forward(&g, &x)
+        .iter()
+        .map(|v| v.data())
+        .collect();
+    assert!(again.iter().zip(&out).all(|(a, o)| *a == o.data()));
+
+    dropout.eval();
+    let eval = dropout.forward(&g, &x);
+    assert!(eval.iter().all(|v| v.data() == 1.0));
+}
+
+#[test]
+fn test_checkpointed_neuron() {
+    let g = Graph::new();
+    let normal = Neuron::new(&g, 64, true);
+    let mut checkpointed = Neuron::new(&g, 64, true);
+    checkpointed.set_checkpointing(true);
+    for (c, n) in checkpointed.parameters().iter().zip(normal.parameters()) {
+        c.set_data(n.data());
+    }
+    let x: Vec<_> = (0..64).map(|i| g.variable(i as f64 / 64.0)).collect();
+
+    let before = g.len();
+    let out_normal = normal.forward(&x);
+    let normal_nodes = g.len() - before;
+    let before = g.len();
+    let out_checkpointed = checkpointed.forward(&x);
+    let checkpointed_nodes = g.len() - before;
+
+    assert_eq!(out_normal.data(), out_checkpointed.data());
+    assert!(checkpointed_nodes < normal_nodes / 10);
+
+    (out_normal + out_checkpointed).backward();
+    for (c, n) in checkpointed.parameters().iter().zip(normal.parameters()) {
+        assert!((c.grad().unwrap() - n.grad().unwrap()).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_layer_norm() {
+    let g = Graph::new();
+    let ln = LayerNorm::new(&g, 4);
+    assert_eq!(ln.parameters().len(), 8);
+
+    let x = [
+        g.variable(1.0),
+        g.variable(2.0),
+        g.variable(4.0),
+        g.variable(9.0),
+    ];
+    let out = ln.forward(&x);
+
+    // gain 1 and bias 0 => output is the plain normalization
+    let values: Vec<f64> = out.iter().map(|v| v.data()).collect();
+    let mean = values.iter().sum::<f64>() / 4.0;
+    let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / 4.0;
+    assert!(mean.abs() < 1e-9);
+    assert!((var - 1.0).abs() < 1e-4);
+
+    // shifting all inputs doesn't change the output => input gradients of sum(out * c) sum to 0
+    let loss = out
+        .iter()
+        .enumerate()
+        .fold(g.variable(0.0), |acc, (i, &o)| {
+            acc + o * g.variable(i as f64)
+        });
+    loss.backward();
+    let grad_sum: f64 = x.iter().map(|xi| xi.grad().unwrap()).sum();
+    assert!(grad_sum.abs() < 1e-9);
+}
+
+#[test]
+fn test_fgsm_attack() {
+    let g = Graph::new();
+    let mlp = g.mlp(3, vec![2]);
+    let weights = [1.0, -2.0, 0.5, 0.0, -1.0, 1.0, 2.0, 0.0]; // [w0.., b0, w1.., b1]
+    for (p, w) in mlp.parameters().iter().zip(weights) {
+        p.set_data(w);
+    }
+    let checkpoint = g.len();
+
+    let loss = |input: &[f64]| {
+        let inputs: Vec<_> = input.iter().map(|&x| g.variable(x)).collect();
+        let loss = g.cross_entropy(&mlp.forward(&inputs), 0).data();
+        g.truncate(checkpoint);
+        loss
+    };
+
+    let input = [0.2, 0.4, 0.6];
+    let adversarial = fgsm_attack(&g, &mlp, &input, 0, 0.1);
+
+    // d loss / dx = (p1 - 0) * (w1 - w0) with p1 > 0 => sign(w1 - w0) = [-, +, +]
+    let expected = [0.1, 0.5, 0.7];
+    for (a, e) in adversarial.iter().zip(expected) {
+        assert!((a - e).abs() < 1e-12);
+    }
+    assert!(loss(&adversarial) > loss(&input));
+    assert_eq!(g.len(), checkpoint);
+}
+
+#[test]
+fn test_batch_norm_1d() {
+    let g = Graph::new();
+    let mut bn = BatchNorm1d::new(&g, 2);
+    let batch: Vec<Vec<_>> = [[1.0, 10.0], [2.0, 20.0], [3.0, 60.0]]
+        .iter()
+        .map(|s| s.iter().map(|&x| g.variable(x)).collect())
+        .collect();
+
+    let out = bn.forward(&batch);
+    for f in 0..2 {
+        let values: Vec<f64> = out.iter().map(|s| s[f].data()).collect();
+        let mean = values.iter().sum::<f64>() / 3.0;
+        let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / 3.0;
+        assert!(mean.abs() < 1e-9);
+        assert!((var - 1.0).abs() < 1e-4);
+    }
+
+    // running stats moved 10% from (0, 1) towards the batch statistics
+    let batch_var0 = 2.0 / 3.0;
+    assert!((bn.running_mean()[0] - 0.2).abs() < 1e-12);
+    assert!((bn.running_var()[0] - (0.9 + 0.1 * batch_var0)).abs() < 1e-12);
+
+    // gradients flow through the batch statistics: sum of a normalized feature is constant
+    let loss = out.iter().fold(g.variable(0.0), |acc, s| acc + s[0]);
+    loss.backward();
+    assert!(batch.iter().all(|s| s[0].grad().unwrap().abs() < 1e-9));
+
+    bn.eval();
+    let eval = bn.forward(&[vec![g.variable(1.0), g.variable(0.0)]]);
+    let expected = (1.0 - 0.2) / (bn.running_var()[0] + 1e-5).sqrt();
+    assert!((eval[0][0].data() - expected).abs() < 1e-12);
+    assert!((bn.running_mean()[0] - 0.2).abs() < 1e-12); // eval doesn't update
+}
+
+#[test]
+fn test_feature_contributions() {
+    let g = Graph::new();
+    let mlp = g.mlp(3, vec![2]);
+    let weights = [1.0, -2.0, 0.5, 0.3, -1.0, 1.0, 2.0, -0.7]; // [w0.., b0, w1.., b1]
+    for (p, w) in mlp.parameters().iter().zip(weights) {
+        p.set_data(w);
+    }
+
+    let input = [0.2, 0.4, 0.6];
+    let contributions = feature_contributions(&g, &mlp, &input, 1);
+    assert_eq!(contributions.len(), 3);
+    assert!((contributions[0] + 0.2).abs() < 1e-12);
+
+    let inputs: Vec<_> = input.iter().map(|&x| g.variable(x)).collect();
+    let logit = mlp.forward(&inputs)[1].data();
+    let bias = -0.7;
+    assert!((contributions.iter().sum::<f64>() + bias - logit).abs() < 1e-12);
+}
+
+#[test]
+fn test_embedding_gradients_only_for_used_rows() {
+    let g = Graph::new();
+    let embedding = Embedding::new(&g, 4, 3);
+    assert_eq!(embedding.parameters().len(), 12);
+
+    let rows = embedding.forward(&[2, 0, 2]);
+    assert_eq!(rows.len(), 3);
+    let loss = rows
+        .iter()
+        .flatten()
+        .fold(g.variable(0.0), |acc, &v| acc + v);
+    loss.backward();
+
+    let grads: Vec<Option<f64>> = embedding.parameters().iter().map(|p| p.grad()).collect();
+    assert_eq!(grads[0..3], [Some(1.0); 3]); // row 0 used once
+    assert_eq!(grads[3..6], [None; 3]); // row 1 unused
+    assert_eq!(grads[6..9], [Some(2.0); 3]); // row 2 used twice
+    assert_eq!(grads[9..12], [None; 3]); // row 3 unused
+}
+
+fn weight_stats(init: Init, nin: i16, nout: i16) -> (f64, f64) {
+    let g = Graph::new();
+    let layer = Layer::with_init(&g, nin, nout, Activation::ReLU, init);
+    // biases are always 0.0, skip them
+    let weights: Vec<f64> = layer
+        .parameters()
+        .chunks(nin as usize + 1)
+        .flat_map(|n| {
+            n[..nin as usize]
+                .iter()
+                .map(|w| w.data())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let n = weights.len() as f64;
+    let mean = weights.iter().sum::<f64>() / n;
+    let var = weights.iter().map(|w| (w - mean).powi(2)).sum::<f64>() / n;
+    (mean, var)
+}
+
+#[test]
+fn test_he_init_variance() {
+    let (mean, var) = weight_stats(Init::He, 100, 200);
+    assert!(mean.abs() < 0.01);
+    assert!((var - 2.0 / 100.0).abs() < 0.1 * 2.0 / 100.0, "var {var}");
+}
+
+#[test]
+fn test_other_inits() {
+    let (_, var) = weight_stats(Init::Xavier, 100, 300);
+    assert!((var - 2.0 / 400.0).abs() < 0.1 * 2.0 / 400.0, "var {var}");
+
+    let (mean, var) = weight_stats(Init::Normal(1.0, 0.5), 100, 200);
+    assert!((mean - 1.0).abs() < 0.02);
+    assert!((var - 0.25).abs() < 0.025, "var {var}");
+
+    assert_eq!(weight_stats(Init::Constant(0.3), 4, 2), (0.3, 0.0));
+}
+
+#[test]
+fn test_seeded_mlp_is_reproducible() {
+    let g = Graph::new();
+    let values = |mlp: &MLP| {
+        mlp.parameters()
+            .iter()
+            .map(|p| p.data())
+            .collect::<Vec<_>>()
+    };
+
+    let a = MLP::seeded(&g, 3, vec![4, 4, 1], 42);
+    let b = MLP::seeded(&g, 3, vec![4, 4, 1], 42);
+    let c = MLP::seeded(&g, 3, vec![4, 4, 1], 43);
+
+    assert_eq!(values(&a), values(&b));
+    assert_ne!(values(&a), values(&c));
+}
+
+#[test]
+fn test_frozen_layer_is_not_updated() {
+    let g = Graph::new();
+    let mlp = MLP::seeded(&g, 3, vec![4, 2], 7);
+    let params = mlp.parameters();
+    let before: Vec<f64> = params.iter().map(|p| p.data()).collect();
+    let first_layer = 3 * 4 + 4;
+
+    mlp.freeze_layer(0);
+    let mut adam = Adam::new(&params, 0.1, 0.9, 0.999, 1e-8);
+    let x = [g.variable(1.0), g.variable(-2.0), g.variable(0.5)];
+    let out = mlp.forward(&x);
+    (out[0] + out[1]).pow(2.0).backward();
+    adam.step();
+    stochastic_gradiant_descent(&params, 0.1);
+
+    let after: Vec<f64> = params.iter().map(|p| p.data()).collect();
+    assert_eq!(before[..first_layer], after[..first_layer]);
+    assert!(
+        before[first_layer..]
+            .iter()
+            .zip(&after[first_layer..])
+            .any(|(b, a)| b != a)
+    );
+    // gradients still flow through the frozen layer
+    assert!(params[..first_layer].iter().any(|p| p.grad().is_some()));
+
+    mlp.unfreeze();
+    assert!(params.iter().all(|p| p.requires_grad()));
+    mlp.freeze();
+    assert!(!params.iter().any(|p| p.requires_grad()));
+}
+
+#[test]
+fn test_num_parameters_and_summary() {
+    let g = Graph::new();
+    let mlp = g.mlp(784, vec![16, 10]);
+
+    assert_eq!(mlp.num_parameters(), mlp.parameters().len());
+    assert_eq!(mlp.num_parameters(), 785 * 16 + 17 * 10);
+
+    let summary = mlp.summary();
+    assert_eq!(summary.lines().count(), 4);
+    assert!(summary.lines().nth(1).unwrap().contains("784"));
+    assert!(summary.ends_with("Total params: 12730\n"));
+}
+
+#[test]
+fn test_summary_of_zero_width_layer() {
+    let g = Graph::new();
+    let mlp = g.mlp(3, vec![0]);
+    let summary = mlp.summary();
+    assert!(summary.lines().nth(1).unwrap().ends_with("None"));
+}
+
+#[test]
+fn test_small_output_scale_starts_near_uniform_loss() {
+    let g = Graph::new();
+    let mlp = MLP::with_output_scale(&g, 784, vec![32, 10], 1e-3);
+    let x: Vec<_> = (0..784)
+        .map(|_| g.variable(rand::random::<f64>()))
+        .collect();
+
+    let loss = g.cross_entropy(&mlp.forward(&x), 3);
+    assert!(
+        (loss.data() - 10f64.ln()).abs() < 0.01,
+        "loss {}",
+        loss.data()
+    );
+}
+
+#[test]
+fn test_effective_rank() {
+    fn layer_with_weights<'a>(g: &'a Graph, w: &[Vec<f64>]) -> Layer<'a> {
+        let nin = w[0].len();
+        let layer = Layer::new(g, nin as i16, w.len() as i16, false);
+        for (params, row) in layer.parameters().chunks(nin + 1).zip(w) {
+            for (p, &v) in params.iter().zip(row) {
+                p.set_data(v);
+            }
+        }
+        layer
+    }
+
+    let g = Graph::new();
+    // outer product u v^T has rank 1
+    let (u, v) = ([1.0, -2.0, 0.5], [0.3, 1.0, -1.0, 2.0]);
+    let rank1: Vec<Vec<f64>> = u
+        .iter()
+        .map(|ui| v.iter().map(|vj| ui * vj).collect())
+        .collect();
+    let rank = layer_with_weights(&g, &rank1).effective_rank();
+    assert!((rank - 1.0).abs() < 1e-6, "rank {rank}");
+
+    // tall matrix with equal singular values
+    let identity = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.0, 0.0]];
+    let rank = layer_with_weights(&g, &identity).effective_rank();
+    assert!((rank - 2.0).abs() < 1e-9, "rank {rank}");
+}
+
+#[test]
+fn test_save_load_weights_round_trip() {
+    let path = std::env::temp_dir().join("backprop_rs_test_weights.json");
+    let g = Graph::new();
+    let mlp = g.mlp(3, vec![4, 2]);
+    let params = mlp.parameters();
+    let x = [g.variable(0.5), g.variable(-1.0), g.variable(2.0)];
+    let out = mlp.forward(&x);
+    (out[0] - out[1]).pow(2.0).backward();
+    stochastic_gradiant_descent(&params, 0.1);
+    mlp.save_weights(&path).unwrap();
+
+    let fresh = g.mlp(3, vec![4, 2]);
+    fresh.load_weights(&path).unwrap();
+    let predict = |m: &MLP| m.forward(&x).iter().map(|v| v.data()).collect::<Vec<_>>();
+    assert_eq!(predict(&mlp), predict(&fresh));
+
+    let other = g.mlp(3, vec![5, 2]);
+    let err = other.load_weights(&path).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_forward_batch_matches_single_forward() {
+    let g = Graph::new();
+    let mlp = g.mlp(2, vec![3, 2]);
+    let inputs: Vec<Vec<_>> = [[0.1, 0.2], [-1.0, 0.5], [2.0, -3.0]]
+        .iter()
+        .map(|x| x.iter().map(|&v| g.variable(v)).collect())
+        .collect();
+
+    let batched = mlp.forward_batch(&inputs);
+    assert_eq!(batched.len(), inputs.len());
+    for (out, x) in batched.iter().zip(&inputs) {
+        let single: Vec<f64> = mlp.forward(x).iter().map(|v| v.data()).collect();
+        let out: Vec<f64> = out.iter().map(|v| v.data()).collect();
+        assert_eq!(out, single);
+    }
+}
+
+#[test]
+fn test_graph_seed_reproduces_dropout_and_noise() {
+    fn run(seed: u64) -> Vec<f64> {
+        let g = Graph::with_seed(seed);
+        assert_eq!(g.seed(), seed);
+        let dropout = Dropout::from_graph_rng(0.5);
+        let x: Vec<_> = (0..16).map(|i| g.variable(i as f64)).collect();
+        let checkpoint = g.len();
+        let mut values = Vec::new();
+        for _ in 0..3 {
+            let out = g.gaussian_noise(&dropout.forward(&g, &x), 0.1);
+            values.extend(out.iter().map(|v| v.data()));
+            g.truncate(checkpoint);
+        }
+        values
+    }
+
+    assert_eq!(run(11), run(11));
+    assert_ne!(run(11), run(12));
+}
+
+#[test]
+fn test_graph_constructors_forward() {
+    let g = Graph::new();
+    let x = g.variables(&[0.5, -1.0, 2.0]);
+
+    let neuron = g.neuron(3, true);
+    assert!(neuron.forward(&x).data() >= 0.0);
+    assert_eq!(g.layer(3, 4, false).forward(&x).len(), 4);
+
+    // a single linear layer computes w . x + b per output
+    let mlp = g.mlp(3, vec![2]);
+    let out = mlp.forward(&x);
+    assert_eq!(out.len(), 2);
+    let params = mlp.parameters();
+    for (o, neuron) in out.iter().zip(params.chunks(4)) {
+        let expected: f64 = neuron[..3]
+            .iter()
+            .zip(&x)
+            .map(|(w, x)| w.data() * x.data())
+            .sum::<f64>()
+            + neuron[3].data();
+        assert!((o.data() - expected).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,258 @@
+use backprop_rs::{
+    graph::Graph,
+    optim::{
+        Adagrad, Adam, AdamW, CosineAnnealingLR, ExponentialLR, MomentumSGD, Nadam, Optimizer,
+        RMSProp, SGD, StepLR, clip_grad_value, newton_step_1d, sgd_with_decay,
+        stochastic_gradiant_descent,
+    },
+};
+
+#[test]
+fn test_adam_converges() {
+    let g = Graph::new();
+    let a = g.variable(0.0);
+    let checkpoint = g.len();
+    let mut adam = Adam::new(&[a], 0.1, 0.9, 0.999, 1e-8);
+
+    for _ in 0..200 {
+        let loss = (a - g.variable(3.0)).pow(2.0);
+        loss.backward();
+        adam.step();
+        g.zero_grad();
+        g.truncate(checkpoint);
+    }
+
+    assert!((a.data() - 3.0).abs() < 1e-2, "a = {}", a.data());
+}
+
+#[test]
+fn test_adamw_decays_without_gradient() {
+    let g = Graph::new();
+    let a = g.variable(1.0);
+    let mut adamw = AdamW::new(&[a], 0.1, 0.9, 0.999, 1e-8, 0.5);
+
+    // no backward => zero gradient, only the decoupled decay acts: a *= 1 - lr * wd
+    for step in 1..=5 {
+        adamw.step();
+        assert!((a.data() - 0.95f64.powi(step)).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_rmsprop_converges() {
+    let g = Graph::new();
+    let a = g.variable(0.0);
+    let checkpoint = g.len();
+    let mut rmsprop = RMSProp::new(&[a], 0.01, 0.99, 1e-8);
+
+    for _ in 0..500 {
+        let loss = (a - g.variable(3.0)).pow(2.0);
+        loss.backward();
+        rmsprop.step();
+        g.zero_grad();
+        g.truncate(checkpoint);
+    }
+
+    assert!((a.data() - 3.0).abs() < 5e-2, "a = {}", a.data());
+}
+
+#[test]
+fn test_momentum_beats_sgd_in_narrow_valley() {
+    // f(x, y) = x^2 + 10 y^2, lr is bounded by the steep y direction.
+    // momentum: None => plain SGD, Some(nesterov) => MomentumSGD
+    fn valley_loss(momentum: Option<bool>) -> f64 {
+        let g = Graph::new();
+        let params = [g.variable(5.0), g.variable(1.0)];
+        let checkpoint = g.len();
+        let mut optim = momentum.map(|nesterov| MomentumSGD::new(&params, 0.01, 0.9, nesterov));
+
+        for _ in 0..100 {
+            let loss = params[0].pow(2.0) + g.variable(10.0) * params[1].pow(2.0);
+            loss.backward();
+            match optim.as_mut() {
+                Some(optim) => optim.step(),
+                None => stochastic_gradiant_descent(&params, 0.01),
+            }
+            g.zero_grad();
+            g.truncate(checkpoint);
+        }
+        params[0].data().powi(2) + 10.0 * params[1].data().powi(2)
+    }
+
+    let sgd = valley_loss(None);
+    let momentum = valley_loss(Some(false));
+    let nesterov = valley_loss(Some(true));
+    assert!(momentum < sgd, "momentum {momentum} vs sgd {sgd}");
+    assert!(nesterov < sgd, "nesterov {nesterov} vs sgd {sgd}");
+}
+
+#[test]
+fn test_adagrad_step_size_shrinks() {
+    let g = Graph::new();
+    let a = g.variable(0.0);
+    let checkpoint = g.len();
+    let mut adagrad = Adagrad::new(&[a], 0.1, 1e-10);
+
+    // constant gradient of 10 => step t is lr / sqrt(t)
+    let mut steps = Vec::new();
+    for _ in 0..10 {
+        let loss = g.variable(10.0) * a;
+        loss.backward();
+        let before = a.data();
+        adagrad.step();
+        steps.push((a.data() - before).abs());
+        g.zero_grad();
+        g.truncate(checkpoint);
+    }
+
+    assert!(steps.windows(2).all(|w| w[1] < w[0]));
+    assert!((steps[3] - 0.1 / 2.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_newton_step_1d_solves_quadratic() {
+    let g = Graph::new();
+    let x = g.variable(10.0);
+    // 2 (x - 3)^2 + 1 has its minimum at x = 3
+    let loss = g.variable(2.0) * (x - g.variable(3.0)).pow(2.0) + g.variable(1.0);
+
+    assert!(newton_step_1d(&g, x, loss));
+
+    assert!((x.data() - 3.0).abs() < 1e-6, "x = {}", x.data());
+    assert!((loss.data() - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_newton_step_1d_rejects_zero_curvature() {
+    let g = Graph::new();
+    let x = g.variable(10.0);
+    let y = g.variable(1.0);
+    let linear = x * 2.0 + 1.0;
+
+    assert!(!newton_step_1d(&g, x, linear));
+    assert_eq!(x.data(), 10.0);
+    assert_eq!(linear.data(), 21.0);
+
+    // no dependency on the parameter
+    assert!(!newton_step_1d(&g, y, linear));
+    assert_eq!(y.data(), 1.0);
+}
+
+#[test]
+fn test_generic_optimizer_loop() {
+    fn minimize<'a>(g: &'a Graph, a: backprop_rs::graph::Variable<'a>, optim: &mut dyn Optimizer) {
+        let checkpoint = g.len();
+        for _ in 0..300 {
+            let loss = (a - g.variable(3.0)).pow(2.0);
+            loss.backward();
+            optim.step();
+            optim.zero_grad();
+            g.truncate(checkpoint);
+        }
+    }
+
+    let g = Graph::new();
+    let a = g.variable(0.0);
+    let b = g.variable(0.0);
+    minimize(&g, a, &mut SGD::new(&[a], 0.1));
+    minimize(&g, b, &mut Adam::new(&[b], 0.1, 0.9, 0.999, 1e-8));
+
+    assert!((a.data() - 3.0).abs() < 1e-6);
+    assert!((b.data() - 3.0).abs() < 1e-2);
+    assert_eq!(a.grad(), None);
+}
+
+#[test]
+fn test_clip_grad_value() {
+    let g = Graph::new();
+    let a = g.variable(0.0);
+    let b = g.variable(0.0);
+    let c = g.variable(0.0);
+    let loss = g.variable(10.0) * a + g.variable(-0.5) * b;
+    loss.backward();
+
+    clip_grad_value(&[a, b, c], 1.0);
+    assert_eq!(a.grad(), Some(1.0));
+    assert_eq!(b.grad(), Some(-0.5));
+    assert_eq!(c.grad(), None);
+}
+
+#[test]
+#[should_panic(expected = "clip must be non-negative")]
+fn test_clip_grad_value_rejects_negative_clip() {
+    let g = Graph::new();
+    clip_grad_value(&[g.variable(0.0)], -1.0);
+}
+
+#[test]
+fn test_sgd_with_decay() {
+    let g = Graph::new();
+    let a = g.variable(2.0);
+
+    // no gradient, only decay: a *= 1 - lr * wd each step
+    let mut expected = 2.0;
+    for _ in 0..5 {
+        sgd_with_decay(&[a], 0.1, 0.5);
+        expected *= 0.95;
+        assert!((a.data() - expected).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_step_lr() {
+    let mut scheduler = StepLR::new(3, 0.5);
+    let lrs: Vec<f64> = (0..8).map(|_| scheduler.get_lr(0.1)).collect();
+    assert_eq!(lrs, vec![0.1, 0.1, 0.1, 0.05, 0.05, 0.05, 0.025, 0.025]);
+}
+
+#[test]
+#[should_panic(expected = "step_size must be positive")]
+fn test_step_lr_rejects_zero_step_size() {
+    StepLR::new(0, 0.5);
+}
+
+#[test]
+fn test_cosine_annealing_lr() {
+    let mut scheduler = CosineAnnealingLR::new(10, 0.001);
+    let lrs: Vec<f64> = (0..=12).map(|_| scheduler.get_lr(0.1)).collect();
+
+    assert!((lrs[0] - 0.1).abs() < 1e-12);
+    assert!((lrs[5] - 0.0505).abs() < 1e-12);
+    assert!((lrs[10] - 0.001).abs() < 1e-12);
+    assert!((lrs[12] - 0.001).abs() < 1e-12);
+    assert!(lrs[..=10].windows(2).all(|w| w[1] < w[0]));
+}
+
+#[test]
+#[should_panic(expected = "t_max must be positive")]
+fn test_cosine_annealing_rejects_zero_t_max() {
+    CosineAnnealingLR::new(0, 0.001);
+}
+
+#[test]
+fn test_exponential_lr() {
+    let mut scheduler = ExponentialLR::new(0.9);
+    assert_eq!(scheduler.get_lr(0.1), 0.1);
+    scheduler.get_lr(0.1);
+    scheduler.get_lr(0.1);
+    // after 3 steps
+    assert!((scheduler.get_lr(0.1) - 0.1 * 0.9f64.powi(3)).abs() < 1e-15);
+}
+
+#[test]
+fn test_nadam_converges() {
+    let g = Graph::new();
+    let a = g.variable(0.0);
+    let checkpoint = g.len();
+    let mut nadam = Nadam::new(&[a], 0.1, 0.9, 0.999, 1e-8);
+
+    for _ in 0..200 {
+        let loss = (a - g.variable(3.0)).pow(2.0);
+        loss.backward();
+        nadam.step();
+        nadam.zero_grad();
+        g.truncate(checkpoint);
+    }
+
+    assert!((a.data() - 3.0).abs() < 1e-2, "a = {}", a.data());
+}
@@ -0,0 +1,91 @@
+use backprop_rs::{graph::Graph, tensor::Tensor};
+
+#[test]
+fn test_tensor_element_access() {
+    let g = Graph::new();
+    let t = Tensor::new(&g, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 2, 3);
+    assert_eq!(t.shape(), (2, 3));
+
+    assert_eq!(t[(0, 0)].data(), 1.0);
+    assert_eq!(t[(0, 2)].data(), 3.0);
+    assert_eq!(t[(1, 0)].data(), 4.0);
+    assert_eq!(t.get(1, 2).map(|v| v.data()), Some(6.0));
+    assert!(t.get(2, 0).is_none());
+    assert!(t.get(0, 3).is_none());
+    let row: Vec<_> = t.row(1).iter().map(|v| v.data()).collect();
+    assert_eq!(row, vec![4.0, 5.0, 6.0]);
+}
+
+#[test]
+#[should_panic(expected = "out of bounds")]
+fn test_tensor_index_out_of_bounds() {
+    let g = Graph::new();
+    let t = Tensor::new(&g, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 2, 3);
+    // (0, 3) would be (1, 0) in the flat buffer
+    let _ = t[(0, 3)];
+}
+
+#[test]
+#[should_panic(expected = "don't fit")]
+fn test_tensor_shape_mismatch() {
+    let g = Graph::new();
+    Tensor::new(&g, &[1.0, 2.0, 3.0], 2, 2);
+}
+
+#[test]
+fn test_matmul_values_and_gradients() {
+    let g = Graph::new();
+    let a = Tensor::new(&g, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 2, 3);
+    let b = Tensor::new(&g, &[7.0, 8.0, 9.0, 10.0, 11.0, 12.0], 3, 2);
+
+    let c = a.matmul(&b);
+    assert_eq!(c.shape(), (2, 2));
+    let values: Vec<_> = c.as_slice().iter().map(|v| v.data()).collect();
+    assert_eq!(values, vec![58.0, 64.0, 139.0, 154.0]);
+
+    // d sum(C) / dA[i][k] = sum_j B[k][j], d sum(C) / dB[k][j] = sum_i A[i][k]
+    g.sum(c.as_slice()).backward();
+    for i in 0..2 {
+        for k in 0..3 {
+            let expected = b.row(k).iter().map(|v| v.data()).sum::<f64>();
+            assert_eq!(a[(i, k)].grad(), Some(expected));
+        }
+    }
+    for k in 0..3 {
+        for j in 0..2 {
+            let expected = a[(0, k)].data() + a[(1, k)].data();
+            assert_eq!(b[(k, j)].grad(), Some(expected));
+        }
+    }
+}
+
+#[test]
+fn test_matmul_without_rows() {
+    let g = Graph::new();
+    let a = Tensor::from_variables(Vec::new(), 0, 3);
+    let b = Tensor::new(&g, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 3, 2);
+    let c = a.matmul(&b);
+    assert_eq!(c.shape(), (0, 2));
+    assert!(c.as_slice().is_empty());
+}
+
+#[test]
+fn test_matmul_with_empty_inner_dimension() {
+    let g = Graph::new();
+    let a = Tensor::new(&g, &[], 2, 0);
+    let b = Tensor::new(&g, &[], 0, 3);
+    let c = a.matmul(&b);
+    assert_eq!(c.shape(), (2, 3));
+    assert!(c.as_slice().iter().all(|v| v.data() == 0.0));
+
+    let no_columns = Tensor::new(&g, &[1.0, 2.0], 2, 1).matmul(&Tensor::new(&g, &[], 1, 0));
+    assert_eq!(no_columns.shape(), (2, 0));
+}
+
+#[test]
+#[should_panic(expected = "inner dimensions")]
+fn test_matmul_shape_mismatch() {
+    let g = Graph::new();
+    let a = Tensor::new(&g, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 2, 3);
+    a.matmul(&a);
+}
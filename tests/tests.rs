@@ -1,4 +1,4 @@
-use backprop_rs::graph::Graph;
+use backprop_rs::graph::{Graph, GraphBridge, LossError, Variable, grad_check};
 
 #[test]
 fn test_add() {
@@ -123,3 +123,1008 @@ fn test_chain() {
     assert_eq!(b.grad().unwrap(), 3.0); // c
     assert_eq!(c.grad().unwrap(), 3.0); // a + b
 }
+
+#[test]
+fn test_cosine_similarity() {
+    let g = Graph::new();
+    let a = [g.variable(1.0), g.variable(2.0), g.variable(3.0)];
+    let b = [g.variable(1.0), g.variable(2.0), g.variable(3.0)];
+    let same = g.cosine_similarity(&a, &b);
+    assert!((same.data() - 1.0).abs() < 1e-9);
+
+    let x = [g.variable(1.0), g.variable(0.0)];
+    let y = [g.variable(0.0), g.variable(2.0)];
+    let orth = g.cosine_similarity(&x, &y);
+    assert!(orth.data().abs() < 1e-9);
+
+    // d/dx0 cos = y0/(|x||y|) - cos * x0/|x|^2 = 0 - 0 = 0; d/dx1 = y1/(|x||y|) = 1
+    orth.backward();
+    assert!(x[0].grad().unwrap().abs() < 1e-9);
+    assert!((x[1].grad().unwrap() - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_apply_brightness() {
+    let g = Graph::new();
+    let pixels = [g.variable(0.1), g.variable(0.5), g.variable(0.9)];
+    let offset = g.variable(0.2);
+    let bright = g.apply_brightness(&pixels, offset);
+
+    assert!((bright[1].data() - 0.7).abs() < 1e-12);
+
+    // loss = sum(bright) => d loss / d offset = number of pixels
+    let loss = bright.iter().skip(1).fold(bright[0], |acc, &x| acc + x);
+    loss.backward();
+    assert_eq!(offset.grad().unwrap(), 3.0);
+    assert_eq!(pixels[0].grad().unwrap(), 1.0);
+}
+
+#[test]
+fn test_abs() {
+    let g = Graph::new();
+    let a = g.variable(-2.0);
+    let b = a.abs();
+
+    assert_eq!(b.data(), 2.0);
+
+    b.backward();
+    assert_eq!(a.grad().unwrap(), -1.0);
+}
+
+#[test]
+fn test_total_variation() {
+    let g = Graph::new();
+    let xs = [g.variable(1.0), g.variable(3.0), g.variable(2.0)];
+    let tv = g.total_variation(&xs);
+
+    assert_eq!(tv.data(), 3.0); // |3-1| + |2-3|
+
+    tv.backward();
+    assert_eq!(xs[0].grad().unwrap(), -1.0);
+    assert_eq!(xs[1].grad().unwrap(), 2.0);
+    assert_eq!(xs[2].grad().unwrap(), -1.0);
+}
+
+#[test]
+fn test_log_det_diag() {
+    let g = Graph::new();
+    let diag = [g.variable(2.0), g.variable(-0.5), g.variable(4.0)];
+    let log_det = g.log_det_diag(&diag);
+
+    // |det| = 2 * 0.5 * 4 = 4
+    assert!((log_det.data() - 4f64.ln()).abs() < 1e-9);
+
+    log_det.backward();
+    for d in diag {
+        assert!((d.grad().unwrap() - 1.0 / d.data()).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn test_tanh() {
+    let g = Graph::new();
+    let a = g.variable(0.5);
+    let b = a.tanh();
+
+    assert_eq!(b.data(), 0.5f64.tanh());
+
+    b.backward();
+    assert!((a.grad().unwrap() - (1.0 - 0.5f64.tanh().powi(2))).abs() < 1e-12);
+}
+
+#[test]
+fn test_sigmoid() {
+    let g = Graph::new();
+    let a = g.variable(0.0);
+    let b = a.sigmoid();
+
+    assert_eq!(b.data(), 0.5);
+
+    b.backward();
+    assert_eq!(a.grad().unwrap(), 0.25);
+}
+
+#[test]
+fn test_leaky_relu() {
+    let g = Graph::new();
+    let a = g.variable(-2.0);
+    let b = a.leaky_relu(0.1);
+
+    assert_eq!(b.data(), -0.2);
+
+    b.backward();
+    assert_eq!(a.grad().unwrap(), 0.1);
+}
+
+#[test]
+fn test_graph_bridge_matches_single_graph() {
+    // loss = (w2 * relu(w1 * x) + w3 * relu(w1 * x) - 1)^2, the hidden value is shared
+    let single = Graph::new();
+    let (w1, w2, w3) = (
+        single.variable(0.5),
+        single.variable(-1.5),
+        single.variable(2.0),
+    );
+    let h = (w1 * single.variable(3.0)).relu();
+    let loss = (w2 * h + w3 * h - single.variable(1.0)).pow(2.0);
+    loss.backward();
+
+    let up = Graph::new();
+    let down = Graph::new();
+    let u1 = up.variable(0.5);
+    let (d2, d3) = (down.variable(-1.5), down.variable(2.0));
+    let uh = (u1 * up.variable(3.0)).relu();
+    let bridge = GraphBridge::new(&[uh], &down);
+    let dh = bridge.inputs()[0];
+    let dloss = (d2 * dh + d3 * dh - down.variable(1.0)).pow(2.0);
+    dloss.backward();
+    bridge.backward();
+
+    assert_eq!(dloss.data(), loss.data());
+    assert_eq!(u1.grad(), w1.grad());
+    assert_eq!(d2.grad(), w2.grad());
+    assert_eq!(d3.grad(), w3.grad());
+}
+
+#[test]
+fn test_hard_sigmoid_linear() {
+    let g = Graph::new();
+    let a = g.variable(1.0);
+    let b = a.hard_sigmoid();
+
+    assert_eq!(b.data(), 0.7);
+
+    b.backward();
+    assert_eq!(a.grad().unwrap(), 0.2);
+}
+
+#[test]
+fn test_hard_sigmoid_saturated() {
+    let g = Graph::new();
+    let low = g.variable(-3.0);
+    let high = g.variable(3.0);
+    let b = low.hard_sigmoid() + high.hard_sigmoid();
+
+    assert_eq!(b.data(), 1.0); // 0 + 1
+
+    b.backward();
+    assert_eq!(low.grad().unwrap(), 0.0);
+    assert_eq!(high.grad().unwrap(), 0.0);
+}
+
+#[test]
+fn test_softsign_at_zero() {
+    let g = Graph::new();
+    let a = g.variable(0.0);
+    let b = a.softsign();
+
+    assert_eq!(b.data(), 0.0);
+
+    b.backward();
+    assert_eq!(a.grad().unwrap(), 1.0);
+}
+
+#[test]
+fn test_softsign_gradcheck() {
+    let eps = 1e-6;
+    for x in [-2.5, -0.3, 0.7, 4.0] {
+        let g = Graph::new();
+        let a = g.variable(x);
+        let b = a.softsign();
+        assert_eq!(b.data(), x / (1.0 + f64::abs(x)));
+        b.backward();
+
+        let f = |x: f64| x / (1.0 + x.abs());
+        let numeric = (f(x + eps) - f(x - eps)) / (2.0 * eps);
+        assert!((a.grad().unwrap() - numeric).abs() < 1e-8);
+    }
+}
+
+#[test]
+fn test_adjacency() {
+    let g = Graph::new();
+    let a = g.variable(1.0);
+    let b = g.variable(2.0);
+    let c = g.variable(3.0);
+    let _ = (a + b) * c;
+
+    assert_eq!(
+        g.adjacency(),
+        vec![
+            (0, "value", 1.0, vec![]),
+            (1, "value", 2.0, vec![]),
+            (2, "value", 3.0, vec![]),
+            (3, "add", 3.0, vec![0, 1]),
+            (4, "mul", 9.0, vec![3, 2]),
+        ]
+    );
+}
+
+#[test]
+fn test_jacobian_sign() {
+    // y = A x with A = [[1, 2], [3, 4]], det(A) = -2
+    let g = Graph::new();
+    let x = [g.variable(0.5), g.variable(-1.0)];
+    let linear = |a: [f64; 4]| {
+        [
+            g.variable(a[0]) * x[0] + g.variable(a[1]) * x[1],
+            g.variable(a[2]) * x[0] + g.variable(a[3]) * x[1],
+        ]
+    };
+
+    let y = linear([1.0, 2.0, 3.0, 4.0]);
+    assert_eq!(g.jacobian(&y, &x), vec![vec![1.0, 2.0], vec![3.0, 4.0]]);
+    assert_eq!(g.jacobian_sign(&y, &x), -1);
+    assert_eq!(x[0].grad(), None);
+
+    let y = linear([4.0, 2.0, 3.0, 4.0]);
+    assert_eq!(g.jacobian_sign(&y, &x), 1);
+    let y = linear([1.0, 2.0, 2.0, 4.0]);
+    assert_eq!(g.jacobian_sign(&y, &x), 0);
+}
+
+#[test]
+fn test_backward_deep_chain() {
+    let g = Graph::new();
+    let x = g.variable(1.0);
+    let mut s = x;
+    for _ in 0..100_000 {
+        s += x;
+    }
+    s.backward();
+
+    assert_eq!(s.data(), 100_001.0);
+    assert_eq!(x.grad(), Some(100_001.0));
+}
+
+#[test]
+fn test_detach() {
+    let g = Graph::new();
+    let a = g.variable(2.0);
+    let b = g.variable(3.0);
+    let c = a * b;
+    let d = c.detach();
+    let e = d * g.variable(4.0) + c;
+    e.backward();
+
+    assert_eq!(d.data(), 6.0);
+    assert_eq!(d.grad(), Some(4.0));
+    // only the non-detached path reaches the inputs
+    assert_eq!(a.grad(), Some(3.0));
+    assert_eq!(b.grad(), Some(2.0));
+}
+
+#[test]
+fn test_backprop_chunked_matches_backward() {
+    let g = Graph::new();
+    let xs: Vec<_> = (0..1000).map(|i| g.variable(i as f64 * 0.01)).collect();
+    let mut loss = g.variable(0.0);
+    for &x in &xs {
+        loss += (x * x).tanh();
+    }
+
+    loss.backward();
+    let expected: Vec<_> = xs.iter().map(|x| x.grad()).collect();
+    g.zero_grad();
+
+    let len = g.len();
+    g.backprop_chunked(loss, 7);
+    let chunked: Vec<_> = xs.iter().map(|x| x.grad()).collect();
+    assert_eq!(chunked, expected);
+
+    // the intermediate variables are released, only xs and the initial zero remain
+    let _reused = g.variables(&vec![0.0; len - xs.len() - 1]);
+    assert_eq!(g.len(), len);
+}
+
+#[test]
+#[should_panic(expected = "still used")]
+fn test_backprop_chunked_rejects_shared_intermediates() {
+    let g = Graph::new();
+    let x = g.variable(2.0);
+    let h = x.exp();
+    let loss = h * 3.0;
+    let _other = h + 1.0;
+    g.backprop_chunked(loss, 1);
+}
+
+#[test]
+fn test_no_grad_records_only_leaves() {
+    let g = Graph::new();
+    let a = g.variable(2.0);
+    let b = g.variable(3.0);
+    let checkpoint = g.len();
+
+    let c = g.no_grad(|| (a * b + a).tanh());
+    assert_eq!(c.data(), 8f64.tanh());
+    assert!(
+        g.adjacency()[checkpoint..]
+            .iter()
+            .all(|(_, op, _, children)| *op == "value" && children.is_empty())
+    );
+    c.backward();
+    assert_eq!(a.grad(), None);
+
+    // recording resumes after the scope
+    let d = a * b;
+    d.backward();
+    assert_eq!(a.grad(), Some(3.0));
+}
+
+#[test]
+fn test_no_grad_restored_after_panic() {
+    let g = Graph::new();
+    let a = g.variable(2.0);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        g.no_grad(|| panic!("inside no_grad"))
+    }));
+    assert!(result.is_err());
+
+    let b = a * a;
+    b.backward();
+    assert_eq!(a.grad(), Some(4.0));
+}
+
+#[test]
+fn test_second_derivative() {
+    let g = Graph::new();
+    let x = g.variable(2.0);
+    let y = x.pow(3.0);
+    y.backward_create_graph();
+
+    let dx = x.grad_var().unwrap();
+    assert_eq!(dx.data(), 12.0); // 3 x^2
+    assert_eq!(x.grad(), Some(12.0));
+
+    g.zero_grad();
+    dx.backward();
+    assert_eq!(x.grad(), Some(12.0)); // 6 x
+}
+
+#[test]
+fn test_grad_var_cleared_by_truncate_and_free() {
+    let g = Graph::new();
+    let x = g.variable(3.0);
+    let checkpoint = g.len();
+
+    (x * x * x).backward_create_graph();
+    assert_eq!(x.grad_var().map(|d| d.data()), Some(27.0));
+    // overwrites instead of accumulating
+    (x * x * x).backward_create_graph();
+    assert_eq!(x.grad(), Some(27.0));
+
+    g.truncate(checkpoint);
+    let _later = g.variables(&[108.0; 20]);
+    assert!(x.grad_var().is_none());
+
+    let y = x * x;
+    y.backward_create_graph();
+    let dx = x.grad_var().unwrap();
+    g.free(&[dx]);
+    assert!(x.grad_var().is_none());
+}
+
+#[test]
+fn test_second_derivative_mixed_ops() {
+    // f(x) = x * exp(x) + tanh(x), f''(x) = (x + 2) exp(x) - 2 tanh(x) (1 - tanh(x)^2)
+    let g = Graph::new();
+    let x = g.variable(0.7);
+    let f = x * x.exp() + x.tanh();
+    f.backward_create_graph();
+    let dx = x.grad_var().unwrap();
+    g.zero_grad();
+    dx.backward();
+
+    let (v, t) = (0.7f64, 0.7f64.tanh());
+    let expected = (v + 2.0) * v.exp() - 2.0 * t * (1.0 - t * t);
+    assert!((x.grad().unwrap() - expected).abs() < 1e-12);
+}
+
+#[test]
+fn test_grad_check() {
+    let (analytic, numeric) = grad_check(|_, x| x.exp(), 0.3, 1e-6);
+    assert!((analytic - numeric).abs() < 1e-8);
+    assert_eq!(analytic, 0.3f64.exp());
+
+    let (analytic, numeric) = grad_check(|g, x| x.pow(3.0) / g.variable(2.0), -1.5, 1e-6);
+    assert!((analytic - numeric).abs() < 1e-8);
+    assert!((analytic - 1.5 * 2.25).abs() < 1e-12);
+}
+
+#[test]
+fn test_to_dot() {
+    let g = Graph::new();
+    let a = g.variable(1.0);
+    let b = g.variable(2.0);
+    let c = g.variable(3.0);
+    let _unrelated = g.variable(4.0);
+    let d = (a + b) * c;
+    d.backward();
+
+    let dot = g.to_dot(d);
+    assert!(dot.starts_with("digraph {"));
+    assert_eq!(dot.matches("[label=").count(), 5);
+    assert_eq!(dot.matches("->").count(), 4);
+    assert!(dot.contains("n5 [label=\"{ #5 | mul | data 9.0000 | grad 1.0000 }\"];"));
+    assert!(dot.contains("n0 -> n4;"));
+    assert!(dot.contains("n4 -> n5;"));
+}
+
+#[test]
+fn test_graph_json_round_trip() {
+    let g = Graph::new();
+    let a = g.variable(1.5);
+    let b = g.variable(-2.0);
+    let c = (a * b).pow(2.0).leaky_relu(0.1) + a.exp();
+    c.backward();
+    let json = g.to_json();
+
+    let reloaded = Graph::from_json(&json).unwrap();
+    assert_eq!(reloaded.len(), g.len());
+    assert_eq!(reloaded.adjacency(), g.adjacency());
+    assert_eq!(reloaded.to_json(), json);
+
+    // re-run backward on the reloaded graph
+    reloaded.zero_grad();
+    reloaded.variable_at(reloaded.len() - 1).unwrap().backward();
+    let (ra, rb) = (
+        reloaded.variable_at(0).unwrap(),
+        reloaded.variable_at(1).unwrap(),
+    );
+    assert_eq!(ra.grad(), a.grad());
+    assert_eq!(rb.grad(), b.grad());
+    assert!(reloaded.variable_at(reloaded.len()).is_none());
+
+    let cyclic = r#"{"vars":[{"data":1.0,"grad":null,"children":[0],"op":"Abs","requires_grad":true}],"free":[]}"#;
+    assert!(Graph::from_json(cyclic).is_err());
+}
+
+#[test]
+fn test_graph_json_rejects_invalid_structure() {
+    let leaf = r#"{"data":1.0,"grad":null,"children":[],"op":"Value","requires_grad":true}"#;
+    let node = |op: &str, children: &str| {
+        format!(
+            r#"{{"data":1.0,"grad":null,"children":{children},"op":{op},"requires_grad":true}}"#
+        )
+    };
+    let graph = |n: String| format!(r#"{{"vars":[{leaf},{leaf},{n}],"free":[]}}"#);
+
+    assert!(Graph::from_json(&graph(node("\"Add\"", "[0,1]"))).is_ok());
+    // wrong arity
+    assert!(Graph::from_json(&graph(node("\"Add\"", "[0]"))).is_err());
+    assert!(Graph::from_json(&graph(node("\"Exp\"", "[0,1]"))).is_err());
+    assert!(Graph::from_json(&graph(node("\"Value\"", "[0]"))).is_err());
+    assert!(Graph::from_json(&graph(node("\"Affine\"", "[0,1]"))).is_err());
+    // children may follow their parent, e.g. in a reused slot, but must exist and not form a cycle
+    let forward_ref = format!(
+        r#"{{"vars":[{leaf},{},{leaf}],"free":[]}}"#,
+        node("\"Add\"", "[0,2]")
+    );
+    assert!(Graph::from_json(&forward_ref).is_ok());
+    assert!(Graph::from_json(&graph(node("\"Add\"", "[0,3]"))).is_err());
+    let cycle = format!(
+        r#"{{"vars":[{leaf},{},{}],"free":[]}}"#,
+        node("\"Exp\"", "[2]"),
+        node("\"Exp\"", "[1]")
+    );
+    assert!(Graph::from_json(&cycle).is_err());
+    // released slots can't be used as children
+    let released_child = format!(
+        r#"{{"vars":[{leaf},{leaf},{}],"free":[1]}}"#,
+        node("\"Add\"", "[0,1]")
+    );
+    assert!(Graph::from_json(&released_child).is_err());
+}
+
+#[test]
+fn test_graph_json_round_trip_after_free() {
+    let g = Graph::new();
+    let a = g.variable(2.0);
+    let tmp = g.variables(&[0.0, 0.0]);
+    let b = g.variable(3.0);
+    g.free(&tmp);
+    let c = a * b; // reuses a released slot before its child b
+    c.backward();
+    let json = g.to_json();
+
+    let reloaded = Graph::from_json(&json).unwrap();
+    assert_eq!(reloaded.to_json(), json);
+    assert_eq!(reloaded.adjacency(), g.adjacency());
+
+    // the remaining released slot is reused instead of growing the arena
+    let d = reloaded.variable(1.0);
+    assert_eq!(reloaded.len(), g.len());
+    assert_eq!(d.data(), 1.0);
+}
+
+#[test]
+fn test_free_reuses_slots() {
+    let g = Graph::new();
+    let w = g.variable(2.0);
+    let b = g.variable(1.0);
+    let model_len = g.len();
+
+    for i in 0..100 {
+        let x = g.variable(i as f64);
+        let wx = w * x;
+        let y = wx + b;
+        y.backward();
+        assert_eq!(w.grad(), Some(i as f64));
+        g.zero_grad();
+        g.free(&[x, wx, y]);
+        assert!(g.len() <= model_len + 3);
+    }
+    assert_eq!(w.data(), 2.0);
+
+    g.compact();
+    assert_eq!(g.len(), model_len);
+    assert_eq!(g.capacity(), model_len);
+}
+
+#[test]
+fn test_truncate_drops_reused_slots_below_checkpoint() {
+    let g = Graph::new();
+    let p = g.variable(3.0);
+    let tmp = g.variable(1.0);
+    let cp = g.len();
+
+    let h = g.variable(4.0);
+    g.free(&[tmp]);
+    let ph = p * h; // reuses the slot of tmp below the checkpoint
+    assert_eq!(ph.data(), 12.0);
+    g.truncate(cp);
+
+    // no variable points past the end of the arena, the slot is released again
+    assert!(
+        g.adjacency()
+            .iter()
+            .all(|(_, _, _, children)| children.is_empty())
+    );
+    let a = g.variable(5.0);
+    let b = g.variable(6.0);
+    assert_eq!(g.len(), cp + 1);
+    assert_eq!((a * b).data(), 30.0);
+}
+
+#[test]
+fn test_truncate_drops_chains_of_reused_slots() {
+    let g = Graph::new();
+    let p = g.variable(3.0);
+    let tmp = g.variables(&[1.0, 2.0]);
+    let cp = g.len();
+
+    let h = g.variable(4.0);
+    g.free(&tmp);
+    let ph = p * h; // both products land in released slots below the checkpoint
+    let pph = p * ph;
+    assert_eq!(g.len(), cp + 1);
+    assert_eq!(pph.data(), 36.0);
+    g.truncate(cp);
+
+    assert!(
+        g.adjacency()
+            .iter()
+            .all(|(_, _, _, children)| children.is_empty())
+    );
+    assert_eq!(g.len(), cp);
+}
+
+#[test]
+fn test_truncate_beyond_len_is_a_no_op() {
+    let g = Graph::new();
+    let a = g.variable(2.0);
+    let b = a * a;
+    g.truncate(g.len() + 1);
+    assert_eq!(g.len(), 2);
+    assert_eq!(b.data(), 4.0);
+}
+
+#[test]
+#[should_panic(expected = "still used")]
+fn test_free_used_variable_panics() {
+    let g = Graph::new();
+    let a = g.variable(1.0);
+    let _b = a.exp();
+    g.free(&[a]);
+}
+
+#[test]
+fn test_negation_adds_no_constant() {
+    let g = Graph::new();
+    let a = g.variable(2.0);
+    let checkpoint = g.len();
+
+    let mut x = a;
+    for _ in 0..1000 {
+        x = -x;
+    }
+    assert_eq!(x.data(), 2.0);
+    // one node per negation, no -1 constant
+    assert_eq!(g.len(), checkpoint + 1000);
+    x.backward();
+    assert_eq!(a.grad(), Some(1.0));
+
+    // a user leaf holding -1 in a slot freed by truncate is never mistaken for a constant
+    g.zero_grad();
+    g.truncate(checkpoint);
+    let _pad = g.variable(0.0);
+    let t = g.variable(-1.0);
+    (-t).backward();
+    assert_eq!(t.grad(), Some(-1.0));
+    let b = a - a;
+    assert_eq!(b.data(), 0.0);
+    b.backward();
+    assert_eq!(a.grad(), Some(0.0));
+    assert_eq!(t.grad(), Some(-1.0));
+}
+
+#[test]
+fn test_scalar_ops() {
+    let g = Graph::new();
+    let a = g.variable(4.0);
+    let checkpoint = g.len();
+
+    let b = a * 3.0;
+    assert_eq!(b.data(), 12.0);
+    b.backward();
+    assert_eq!(a.grad(), Some(3.0));
+    g.zero_grad();
+
+    let c = 2.0 - a;
+    assert_eq!(c.data(), -2.0);
+    c.backward();
+    assert_eq!(a.grad(), Some(-1.0));
+    g.zero_grad();
+
+    let d = (a + 1.0) / 2.0 + 8.0 / a - 0.5 + 2.0 * a;
+    assert_eq!(d.data(), 2.5 + 2.0 - 0.5 + 8.0);
+    d.backward();
+    assert_eq!(a.grad(), Some(0.5 - 0.5 + 2.0));
+
+    // only op nodes, no constants
+    assert!(
+        g.adjacency()[checkpoint..]
+            .iter()
+            .all(|(_, op, _, _)| *op != "value")
+    );
+}
+
+#[test]
+fn test_sum_and_mean() {
+    let g = Graph::new();
+    let xs: Vec<_> = (1..=5).map(|i| g.variable(i as f64)).collect();
+
+    let s = g.sum(&xs);
+    assert_eq!(s.data(), 15.0);
+    s.backward();
+    assert!(xs.iter().all(|x| x.grad() == Some(1.0)));
+    g.zero_grad();
+
+    let m = g.mean(&xs);
+    assert_eq!(m.data(), 3.0);
+    m.backward();
+    assert!(xs.iter().all(|x| x.grad() == Some(0.2)));
+
+    assert_eq!(g.sum(&[]).data(), 0.0);
+    assert_eq!(g.mean(&[]).data(), 0.0);
+}
+
+#[test]
+fn test_sum_is_balanced() {
+    let g = Graph::new();
+    let xs: Vec<_> = (0..1024).map(|_| g.variable(1.0)).collect();
+    let s = g.sum(&xs);
+    assert_eq!(s.data(), 1024.0);
+
+    // the path from the root to any leaf has log2(1024) additions
+    let adjacency = g.adjacency();
+    let mut depth = 0;
+    let mut node = adjacency.len() - 1;
+    while let Some(&child) = adjacency[node].3.first() {
+        node = child;
+        depth += 1;
+    }
+    assert_eq!(depth, 10);
+}
+
+#[test]
+fn test_dot_gradients() {
+    let g = Graph::new();
+    let a: Vec<_> = [1.0, -2.0, 0.5].iter().map(|&x| g.variable(x)).collect();
+    let b: Vec<_> = [4.0, 3.0, -6.0].iter().map(|&x| g.variable(x)).collect();
+
+    let d = g.dot(&a, &b);
+    assert_eq!(d.data(), 4.0 - 6.0 - 3.0);
+    d.backward();
+    for (x, y) in a.iter().zip(&b) {
+        assert_eq!(x.grad(), Some(y.data()));
+        assert_eq!(y.grad(), Some(x.data()));
+    }
+}
+
+#[test]
+#[should_panic(expected = "equal length")]
+fn test_dot_length_mismatch() {
+    let g = Graph::new();
+    let a = [g.variable(1.0), g.variable(2.0)];
+    g.dot(&a, &a[..1]);
+}
+
+#[test]
+fn test_l2_penalty_gradient() {
+    let g = Graph::new();
+    let params = g.variables(&[1.5, -0.5, 0.0]);
+    let checkpoint = g.len();
+
+    let penalty = g.l2_penalty(&params);
+    assert_eq!(penalty.data(), 2.25 + 0.25);
+    penalty.backward_and_free(checkpoint);
+    for p in &params {
+        assert_eq!(p.grad(), Some(2.0 * p.data()));
+    }
+}
+
+#[test]
+fn test_l1_penalty_gradient_sign() {
+    let g = Graph::new();
+    let params = g.variables(&[1.5, -0.5, 0.0]);
+
+    let penalty = g.l1_penalty(&params);
+    assert_eq!(penalty.data(), 2.0);
+    penalty.backward();
+    let grads: Vec<_> = params.iter().map(|p| p.grad()).collect();
+    assert_eq!(grads, vec![Some(1.0), Some(-1.0), Some(0.0)]);
+}
+
+#[test]
+fn test_variable_formatting() {
+    let g = Graph::new();
+    let a = g.variable(1.0);
+    let b = g.variable(2.0);
+    assert_eq!(format!("{b}"), "Var(data=2.0, grad=None)");
+
+    (a * b).backward();
+    assert_eq!(format!("{b}"), "Var(data=2.0, grad=Some(1.0))");
+    assert_eq!(format!("{b:.2}"), "Var(data=2.00, grad=Some(1.00))");
+    assert_eq!(format!("{b:?}"), "Var(#1, data=2.0, grad=Some(1.0))");
+}
+
+#[test]
+fn test_compound_assignment() {
+    let g = Graph::new();
+    let a = g.variable(3.0);
+    let b = g.variable(-2.0);
+
+    let mut c = a;
+    c += b;
+    c -= a;
+    c *= b;
+    c /= a;
+    c += 1.0;
+    c -= 0.5;
+    c *= 2.0;
+    c /= 4.0;
+
+    let d = (((((a + b - a) * b / a) + 1.0) - 0.5) * 2.0) / 4.0;
+    assert_eq!(c.data(), d.data());
+
+    c.backward();
+    let (ga, gb) = (a.grad(), b.grad());
+    g.zero_grad();
+    d.backward();
+    assert_eq!((a.grad(), b.grad()), (ga, gb));
+}
+
+#[test]
+fn test_sum_iterator() {
+    let g = Graph::new();
+    let xs = g.variables(&[1.0, -2.0, 3.5, 0.25]);
+
+    let manual = xs[1..].iter().fold(xs[0], |acc, &x| acc + x);
+    let s: Variable = xs.iter().copied().sum();
+    assert_eq!(s.data(), manual.data());
+    assert_eq!(xs.iter().sum::<Variable>().data(), manual.data());
+
+    s.backward();
+    assert!(xs.iter().all(|x| x.grad() == Some(1.0)));
+}
+
+#[test]
+#[should_panic(expected = "empty")]
+fn test_sum_iterator_empty() {
+    let _: Variable = std::iter::empty::<Variable>().sum();
+}
+
+/// The usage example of the README, `graph` is the only engine
+#[test]
+fn test_readme_usage() {
+    let g = Graph::new();
+    let mlp = g.mlp(2, vec![8, 8, 1]);
+
+    let inputs = [g.variable(1.0), g.variable(0.0)];
+    let pred = mlp.forward(&inputs);
+
+    let target = g.variable(1.0);
+    let loss = (pred[0] - target) * (pred[0] - target);
+
+    loss.backward();
+    assert!(mlp.parameters().iter().any(|p| p.grad().is_some()));
+}
+
+#[test]
+fn test_forward_recomputes_after_set_data() {
+    let g = Graph::new();
+    let x = g.variable(2.0);
+    let w = g.variable(3.0);
+    let y = (w * x + 1.0).pow(2.0) / x;
+    assert_eq!(y.data(), 24.5);
+    let len = g.len();
+
+    x.set_data(1.0);
+    w.set_data(-2.0);
+    assert_eq!(y.data(), 24.5);
+    g.forward(y);
+    assert_eq!(y.data(), 1.0);
+    assert_eq!(g.len(), len);
+
+    // gradients follow the refreshed values
+    y.backward();
+    assert_eq!(w.grad(), Some(-2.0));
+}
+
+#[test]
+fn test_check_finite() {
+    let g = Graph::new();
+    let a = g.variable(2.0);
+    let b = g.variable(0.0);
+    let c = a * b;
+    assert_eq!(g.check_finite(), Ok(()));
+    c.backward();
+    assert_eq!(g.check_finite(), Ok(()));
+
+    // variable 3 is log(0) = -inf
+    let _ = b.log() + c;
+    assert_eq!(g.check_finite(), Err(3));
+
+    // finite values, but the gradient of sqrt at 0 is infinite
+    let g = Graph::new();
+    let x = g.variable(0.0);
+    x.sqrt().backward();
+    assert_eq!(g.check_finite(), Err(0));
+}
+
+#[test]
+#[should_panic(expected = "log op computed the non-finite value -inf")]
+fn test_panic_on_nan_forward() {
+    let g = Graph::new();
+    g.set_panic_on_nan(true);
+    g.variable(0.0).log();
+}
+
+#[test]
+#[should_panic(expected = "non-finite gradient inf")]
+fn test_panic_on_nan_backward() {
+    let g = Graph::new();
+    g.set_panic_on_nan(true);
+    g.variable(0.0).sqrt().backward();
+}
+
+#[test]
+fn test_variables_from_slice() {
+    let g = Graph::with_capacity(16);
+    assert!(g.capacity() >= 16);
+    let a = g.variable(7.0);
+
+    let xs = g.variables(&[1.0, -2.0, 3.5]);
+    assert_eq!(
+        xs.iter().map(|x| x.data()).collect::<Vec<_>>(),
+        vec![1.0, -2.0, 3.5]
+    );
+    assert_eq!(g.len(), 4);
+
+    let s = g.sum(&xs) * a;
+    s.backward();
+    assert!(xs.iter().all(|x| x.grad() == Some(7.0)));
+}
+
+#[test]
+fn test_backward_and_free() {
+    let g = Graph::new();
+    let w = g.variable(3.0);
+    let checkpoint = g.len();
+
+    let x = g.variable(2.0);
+    let loss = (w * x).pow(2.0);
+    loss.backward_and_free(checkpoint);
+
+    assert_eq!(g.len(), checkpoint);
+    assert_eq!(w.grad(), Some(24.0));
+    assert!(g.variable_at(checkpoint).is_none());
+}
+
+#[test]
+fn test_repeated_backward_reuses_topo_buffer() {
+    let g = Graph::new();
+    let x = g.variable(1.0);
+    let checkpoint = g.len();
+
+    for n in 1..200 {
+        let mut s = x;
+        for _ in 0..n {
+            s += x;
+        }
+        s.backward();
+        assert_eq!(x.grad(), Some(n as f64 + 1.0));
+        g.zero_grad();
+        g.truncate(checkpoint);
+    }
+}
+
+#[test]
+#[should_panic(expected = "different graphs")]
+fn test_mixing_graphs_panics() {
+    let g1 = Graph::new();
+    let g2 = Graph::new();
+    let a = g1.variable(1.0);
+    let b = g2.variable(2.0);
+    let _ = a * b;
+}
+
+#[test]
+fn test_backward_accumulates_backward_replace_does_not() {
+    let g = Graph::new();
+    let a = g.variable(3.0);
+    let b = g.variable(1.0);
+    let loss = (a - b).pow(2.0);
+
+    loss.backward();
+    loss.backward_replace();
+    loss.backward_replace();
+    assert_eq!(a.grad(), Some(4.0));
+    assert_eq!(b.grad(), Some(-4.0));
+
+    // plain backward accumulates: the intermediate a - b keeps its gradient from the first call
+    // and propagates the sum again, so a second call triples the gradients
+    g.zero_grad();
+    let loss = (a - b).pow(2.0);
+    loss.backward();
+    loss.backward();
+    assert_eq!(a.grad(), Some(12.0));
+}
+
+#[test]
+fn test_try_cross_entropy() {
+    let g = Graph::new();
+    let logits = g.variables(&[0.25f64.ln(), 0.75f64.ln()]);
+
+    let loss = g.try_cross_entropy(&logits, 1).unwrap();
+    assert!((loss.data() + 0.75f64.ln()).abs() < 1e-12);
+
+    let err = g.try_cross_entropy(&logits, 7).unwrap_err();
+    assert_eq!(
+        err,
+        LossError::TargetOutOfRange {
+            target: 7,
+            num_classes: 2
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "target class 7 is out of range for 2 classes"
+    );
+}
+
+#[test]
+fn test_cross_entropy_with_vanishing_probability() {
+    let g = Graph::new();
+    // exp(-800) underflows to 0 in the softmax
+    let logits = g.variables(&[0.0, -800.0]);
+    assert_eq!(g.softmax(&logits)[1].data(), 0.0);
+
+    let loss = g.cross_entropy(&logits, 1);
+    assert_eq!(loss.data(), 800.0);
+    loss.backward();
+    // softmax - onehot: raise the target logit, lower the other one
+    assert_eq!(logits[1].grad(), Some(-1.0));
+    assert_eq!(logits[0].grad(), Some(1.0));
+}
@@ -0,0 +1,278 @@
+use std::ops::ControlFlow;
+
+use backprop_rs::{
+    data::{CsvData, DataLoader},
+    graph::{Graph, Variable},
+    nn::MLP,
+    optim::{Adam, Optimizer, SGD, stochastic_gradiant_descent},
+    train::{
+        BatchReduction, Callback, EarlyStopping, EmaLoss, TrainConfig, TrainGuard, Trainer,
+        parallel_batch_gradients,
+    },
+};
+
+/// Train y = w * x + b on a fixed batch and return the parameter trajectory
+fn trajectory(reduction: BatchReduction, lr: f64) -> Vec<(f64, f64)> {
+    let xs = [1.0, 2.0, 3.0, 4.0];
+    let ys = [3.0, 5.0, 7.0, 9.0];
+
+    let g = Graph::new();
+    let w = g.variable(0.5);
+    let b = g.variable(-0.5);
+    let checkpoint = g.len();
+
+    let mut traj = Vec::new();
+    for _ in 0..20 {
+        let losses: Vec<_> = xs
+            .iter()
+            .zip(ys)
+            .map(|(&x, y)| (w * g.variable(x) + b - g.variable(y)).pow(2.0))
+            .collect();
+        reduction.reduce(&g, &losses).backward();
+        stochastic_gradiant_descent(&[w, b], lr);
+        g.zero_grad();
+        g.truncate(checkpoint);
+        traj.push((w.data(), b.data()));
+    }
+    traj
+}
+
+#[test]
+fn test_batch_reduction_mean_matches_scaled_sum() {
+    let lr = 0.01;
+    let mean = trajectory(BatchReduction::Mean, lr);
+    let sum = trajectory(BatchReduction::Sum, lr / 4.0);
+    for ((wm, bm), (ws, bs)) in mean.iter().zip(&sum) {
+        assert!((wm - ws).abs() < 1e-12);
+        assert!((bm - bs).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_train_config_round_trip() {
+    let config = TrainConfig {
+        lr: 0.01,
+        batch_size: 32,
+        epochs: 10,
+        optimizer: "sgd".to_string(),
+        seed: 42,
+        nin: 784,
+        nouts: vec![16, 10],
+    };
+
+    let json = config.to_json();
+    assert_eq!(TrainConfig::from_json(&json).unwrap(), config);
+
+    let path = std::env::temp_dir().join(format!("train_config_{}.json", std::process::id()));
+    config.save(&path).unwrap();
+    let loaded = TrainConfig::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, config);
+}
+
+#[test]
+fn test_train_guard_recovers_from_nan() {
+    let g = Graph::new();
+    let a = g.variable(0.0);
+    let checkpoint = g.len();
+    let mut guard = TrainGuard::new(&[a], SGD::new(&[a], 0.1));
+
+    // good step: grad of (a - 3)^2 at 0 is -6 => a = 0.6
+    assert!(guard.step((a - g.variable(3.0)).pow(2.0)));
+    g.truncate(checkpoint);
+    assert!((a.data() - 0.6).abs() < 1e-12);
+
+    // NaN step is rejected, a stays at the checkpoint and lr is halved
+    assert!(!guard.step(a * g.variable(f64::NAN)));
+    g.truncate(checkpoint);
+    assert!((a.data() - 0.6).abs() < 1e-12);
+    assert_eq!(a.grad(), None);
+    assert_eq!(guard.optimizer().lr(), 0.05);
+
+    // training resumes from the checkpoint with the reduced lr: grad -4.8 => a = 0.84
+    assert!(guard.step((a - g.variable(3.0)).pow(2.0)));
+    assert!((a.data() - 0.84).abs() < 1e-12);
+}
+
+#[test]
+fn test_ema_loss_is_smoother() {
+    let raw: Vec<f64> = (0..100)
+        .map(|i| 1.0 + if i % 2 == 0 { 0.5 } else { -0.5 })
+        .collect();
+    let mut ema = EmaLoss::new(0.9);
+    let smoothed: Vec<f64> = raw.iter().map(|&l| ema.update(l)).collect();
+
+    let variance = |xs: &[f64]| {
+        let mean = xs.iter().sum::<f64>() / xs.len() as f64;
+        xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / xs.len() as f64
+    };
+    assert_eq!(smoothed[0], raw[0]);
+    assert!(variance(&smoothed) < variance(&raw) / 10.0);
+    assert!((ema.value().unwrap() - 1.0).abs() < 0.1);
+}
+
+#[test]
+fn test_gradient_accumulation_over_microbatches() {
+    let g = Graph::new();
+    let mlp = MLP::seeded(&g, 2, vec![3, 1], 3);
+    let params = mlp.parameters();
+    let checkpoint = g.len();
+    let batch = [([0.5, -1.0], 1.0), ([2.0, 0.3], -0.5), ([-1.5, 0.8], 0.25)];
+    let loss = |samples: &[([f64; 2], f64)]| {
+        let losses: Vec<_> = samples
+            .iter()
+            .map(|(x, y)| (mlp.forward(&g.variables(x))[0] - *y).pow(2.0))
+            .collect();
+        g.sum(&losses)
+    };
+
+    loss(&batch).backward();
+    let full: Vec<_> = params.iter().map(|p| p.grad().unwrap()).collect();
+    g.zero_grad();
+    g.truncate(checkpoint);
+
+    // two microbatches, no zero_grad in between
+    loss(&batch[..1]).backward();
+    g.truncate(checkpoint);
+    loss(&batch[1..]).backward();
+    g.truncate(checkpoint);
+
+    for (p, expected) in params.iter().zip(full) {
+        assert!((p.grad().unwrap() - expected).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_parallel_batch_gradients_match_sequential() {
+    let samples: Vec<([f64; 2], f64)> = (0..37)
+        .map(|i| {
+            let x = [(i as f64 * 0.37).sin(), (i as f64 * 0.11).cos()];
+            (x, x[0] * x[1])
+        })
+        .collect();
+    fn sample_loss<'a>(mlp: &MLP<'a>, g: &'a Graph, (x, y): &([f64; 2], f64)) -> Variable<'a> {
+        let pred = mlp.forward(&g.variables(x))[0];
+        (pred - *y).pow(2.0)
+    }
+
+    let g = Graph::new();
+    let mlp = MLP::seeded(&g, 2, vec![8, 1], 5);
+    let params = mlp.parameters();
+    let losses: Vec<_> = samples.iter().map(|s| sample_loss(&mlp, &g, s)).collect();
+    g.sum(&losses).backward();
+
+    let parallel = parallel_batch_gradients(&params, &samples, |g, params, sample| {
+        let mlp = MLP::from_parameters(2, vec![8, 1], params);
+        sample_loss(&mlp, g, sample)
+    });
+
+    assert_eq!(parallel.len(), params.len());
+    for (p, grad) in params.iter().zip(parallel) {
+        assert!((p.grad().unwrap_or_default() - grad).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn test_trainer_fits_xor() {
+    let xor = CsvData {
+        features: vec![
+            vec![0.0, 0.0],
+            vec![0.0, 1.0],
+            vec![1.0, 0.0],
+            vec![1.0, 1.0],
+        ],
+        labels: vec![0, 1, 1, 0],
+    };
+    let g = Graph::new();
+    let mlp = MLP::seeded(&g, 2, vec![16, 1], 1);
+    let optimizer = Adam::new(&mlp.parameters(), 0.05, 0.9, 0.999, 1e-8);
+    let loader = DataLoader::new(&xor, 4, 0);
+    let checkpoint = g.len();
+
+    let mut trainer = Trainer::new(
+        &g,
+        &mlp,
+        optimizer,
+        |out, label| (out[0] - label as f64).pow(2.0),
+        loader,
+    );
+    let losses = trainer.fit(300);
+    assert_eq!(losses.len(), 300);
+    assert!(losses[299] < 0.01, "final loss {}", losses[299]);
+    // the batch nodes are freed again
+    assert_eq!(g.len(), checkpoint);
+
+    for (x, &y) in xor.features.iter().zip(&xor.labels) {
+        let pred = mlp.forward(&g.variables(x))[0].data();
+        assert!((pred - y as f64).abs() < 0.2, "{x:?} -> {pred}");
+    }
+}
+
+/// Records the epoch losses and stops after `max_epochs`
+struct Recorder {
+    epoch_losses: Vec<f64>,
+    batches: usize,
+    max_epochs: usize,
+}
+
+impl Callback for Recorder {
+    fn on_batch_end(&mut self, _epoch: usize, _batch: usize, _loss: f64) {
+        self.batches += 1;
+    }
+
+    fn on_epoch_end(&mut self, epoch: usize, loss: f64) -> ControlFlow<()> {
+        assert_eq!(epoch, self.epoch_losses.len());
+        self.epoch_losses.push(loss);
+        if self.epoch_losses.len() == self.max_epochs {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+#[test]
+fn test_trainer_callbacks() {
+    let data = CsvData {
+        features: (0..6).map(|x| vec![x as f64]).collect(),
+        labels: (0..6).map(|x| 2 * x + 1).collect(),
+    };
+    let g = Graph::new();
+    let mlp = MLP::seeded(&g, 1, vec![1], 0);
+    let optimizer = SGD::new(&mlp.parameters(), 0.01);
+    let loader = DataLoader::new(&data, 4, 0);
+    let mut trainer = Trainer::new(
+        &g,
+        &mlp,
+        optimizer,
+        |out, label| (out[0] - label as f64).pow(2.0),
+        loader,
+    );
+
+    let mut recorder = Recorder {
+        epoch_losses: Vec::new(),
+        batches: 0,
+        max_epochs: 5,
+    };
+    let losses = trainer.fit_with_callbacks(20, &mut [&mut recorder]);
+    assert_eq!(losses.len(), 5);
+    assert_eq!(recorder.epoch_losses, losses);
+    // two batches per epoch
+    assert_eq!(recorder.batches, 10);
+}
+
+#[test]
+fn test_early_stopping_on_plateau() {
+    let losses = [1.0, 0.8, 0.7, 0.7, 0.69, 0.71, 0.7];
+    let mut early = EarlyStopping::new(3, 0.05);
+    let stopped: Vec<bool> = losses.iter().map(|&l| early.should_stop(l)).collect();
+    // 0.7 is the last improvement by more than 0.05, three epochs later it stops
+    assert_eq!(stopped, vec![false, false, false, false, false, true, true]);
+    assert_eq!(early.best(), 0.7);
+}
+
+#[test]
+#[should_panic(expected = "patience must be positive")]
+fn test_early_stopping_rejects_zero_patience() {
+    EarlyStopping::new(0, 0.0);
+}